@@ -0,0 +1,29 @@
+#![no_main]
+
+use cargo_crate::utils::context::{DepInfo, PackageInfo, StringTable};
+use cargo_crate::utils::package::CratePackage;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(crate_package) = CratePackage::decode_from_slice(data) else {
+        return;
+    };
+    //walk everything the decoder hands out, the fingerprint is not checked here
+    let mut str_table = StringTable::new();
+    if str_table.read_bytes(&crate_package.string_table).is_err() {
+        return;
+    }
+    if let Ok(package_section) = crate_package.package_section() {
+        let _ = PackageInfo::default().read_from_package_section(package_section, &str_table);
+    }
+    if let Ok(dep_table) = crate_package.dep_table_section() {
+        for entry in dep_table.entries.arr.iter() {
+            let _ = DepInfo::default().read_from_dep_table_entry(entry, &str_table);
+        }
+    }
+    for no in 0..crate_package.section_index.sig_num() {
+        let _ = crate_package.sig_structure_section(no);
+    }
+    let _ = crate_package.crate_binary_section();
+    let _ = crate_package.section_index.datasection_size_without_sig();
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use cargo_crate::utils::context::StringTable;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut str_table = StringTable::new();
+    if str_table.read_bytes(data).is_ok() {
+        //a table that parses must dump back to the same bytes
+        assert_eq!(str_table.to_bytes(), data);
+    }
+});
@@ -0,0 +1,420 @@
+use crate::utils::context::{
+    read_from_features_section, read_from_targets_section, sig_type_name, DepInfo, GitRef,
+    LockedPackage, PackageInfo, SrcTypePath, StringTable, TargetInfo, VcsInfo,
+};
+use crate::utils::error::Error;
+use crate::utils::package::view::ScrateFile;
+use crate::utils::package::{
+    datasection_name, format_version, has_wide_offsets, is_critical, CrateHeader, CratePackage,
+    DataSection, Off, Size, Type,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+///string table entry
+#[derive(Debug, Serialize)]
+pub struct StrEntry {
+    pub offset: u32,
+    pub value: String,
+}
+
+///section index entry
+#[derive(Debug, Serialize)]
+pub struct SectionEntry {
+    pub sh_type: Type,
+    pub name: &'static str,
+    ///readers that don't know the type must reject the package
+    pub critical: bool,
+    pub sh_offset: Off,
+    pub sh_size: Size,
+}
+
+///signature structure section
+#[derive(Debug, Serialize)]
+pub struct SigEntry {
+    pub sigstruct_type: Type,
+    pub name: &'static str,
+    pub sigstruct_size: Size,
+}
+
+///everything inside a `.scrate`, read without checking fingerprint or signatures
+#[derive(Debug, Serialize)]
+pub struct Inspection {
+    pub magic: String,
+    pub header: CrateHeader,
+    pub string_table: Vec<StrEntry>,
+    pub section_index: Vec<SectionEntry>,
+    pub package: PackageInfo,
+    pub dependencies: Vec<DepInfo>,
+    pub crate_binary_size: usize,
+    pub vcs: Option<VcsInfo>,
+    pub locked_packages: Vec<LockedPackage>,
+    pub features: BTreeMap<String, Vec<String>>,
+    pub targets: Vec<TargetInfo>,
+    pub signatures: Vec<SigEntry>,
+    pub fingerprint: String,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Inspection {
+    pub fn from_crate_package(crate_package: CratePackage) -> Result<Self, Error> {
+        let mut str_table = StringTable::new();
+        str_table.read_bytes(&crate_package.string_table)?;
+
+        let mut package = PackageInfo::default();
+        package.read_from_package_section(crate_package.package_section()?, &str_table)?;
+
+        let mut dependencies = vec![];
+        for entry in crate_package.dep_table_section()?.entries.arr.iter() {
+            let mut dep_info = DepInfo::default();
+            dep_info.read_from_dep_table_entry(entry, &str_table)?;
+            dependencies.push(dep_info);
+        }
+
+        let features = match crate_package.features_section()? {
+            Some(features) => read_from_features_section(features, &str_table)?,
+            None => BTreeMap::new(),
+        };
+        let targets = match crate_package.targets_section()? {
+            Some(targets) => read_from_targets_section(targets, &str_table)?,
+            None => vec![],
+        };
+
+        let mut signatures = vec![];
+        for section in crate_package.data_sections.col.arr.iter() {
+            if let DataSection::SigStructureSection(sig) = section {
+                signatures.push(SigEntry {
+                    sigstruct_type: sig.sigstruct_type,
+                    name: sig_type_name(sig.sigstruct_type as u32),
+                    sigstruct_size: sig.sigstruct_size,
+                });
+            }
+        }
+
+        let crate_binary_size = crate_package.crate_binary_section()?.bin.len();
+        let vcs = match crate_package.vcs_info_section()? {
+            Some(vcs) => Some(VcsInfo::read_from_vcs_info_section(vcs, &str_table)?),
+            None => None,
+        };
+        let mut locked_packages = vec![];
+        if let Some(lock) = crate_package.lockfile_section()? {
+            for entry in lock.entries.arr.iter() {
+                locked_packages.push(LockedPackage::read_from_lockfile_entry(entry, &str_table)?);
+            }
+        }
+        Ok(Self {
+            magic: to_hex(&crate_package.magic_number),
+            string_table: str_table
+                .entries()
+                .into_iter()
+                .map(|(offset, value)| StrEntry { offset, value })
+                .collect(),
+            section_index: crate_package
+                .section_index
+                .entries
+                .arr
+                .iter()
+                .map(|entry| SectionEntry {
+                    sh_type: entry.sh_type,
+                    name: datasection_name(entry.sh_type),
+                    critical: is_critical(entry.sh_type),
+                    sh_offset: entry.sh_offset,
+                    sh_size: entry.sh_size,
+                })
+                .collect(),
+            package,
+            dependencies,
+            crate_binary_size,
+            vcs,
+            locked_packages,
+            features,
+            targets,
+            signatures,
+            fingerprint: to_hex(&crate_package.finger_print),
+            header: crate_package.crate_header,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::Serialize(e.to_string()))
+    }
+}
+
+///optional, default-features, renamed package, git ref and features of a dependency
+fn dep_options(dep: &DepInfo) -> String {
+    let mut options = vec![];
+    if dep.optional {
+        options.push("optional".to_string());
+    }
+    if !dep.default_features {
+        options.push("no-default-features".to_string());
+    }
+    if !dep.package.is_empty() {
+        options.push(format!("package={}", dep.package));
+    }
+    match &dep.git_ref {
+        GitRef::DefaultBranch => {}
+        GitRef::Branch(name) => options.push(format!("branch={}", name)),
+        GitRef::Tag(name) => options.push(format!("tag={}", name)),
+        GitRef::Rev(name) => options.push(format!("rev={}", name)),
+    }
+    if !dep.features.is_empty() {
+        options.push(format!("features={}", dep.features.join(",")));
+    }
+    options.join(" ")
+}
+
+impl Display for Inspection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "magic: {}", self.magic)?;
+
+        writeln!(f, "\nheader:")?;
+        let h = &self.header;
+        if has_wide_offsets(h.c_version) {
+            writeln!(
+                f,
+                "  {:<16}{} (8-byte offsets)",
+                "c_version",
+                format_version(h.c_version)
+            )?;
+        } else {
+            writeln!(f, "  {:<16}{}", "c_version", h.c_version)?;
+        }
+        writeln!(f, "  {:<16}{}", "strtable_size", h.strtable_size)?;
+        writeln!(f, "  {:<16}{}", "strtable_offset", h.strtable_offset)?;
+        writeln!(f, "  {:<16}{}", "si_size", h.si_size)?;
+        writeln!(f, "  {:<16}{}", "si_offset", h.si_offset)?;
+        writeln!(f, "  {:<16}{}", "si_num", h.si_num)?;
+        writeln!(f, "  {:<16}{}", "ds_offset", h.ds_offset)?;
+
+        writeln!(f, "\nstring table ({} entries):", self.string_table.len())?;
+        writeln!(f, "  {:<10}VALUE", "OFFSET")?;
+        for entry in self.string_table.iter() {
+            writeln!(f, "  {:<10}{:?}", entry.offset, entry.value)?;
+        }
+
+        writeln!(f, "\nsection index ({} entries):", self.section_index.len())?;
+        writeln!(
+            f,
+            "  {:<6}{:<16}{:<10}{:<12}SIZE",
+            "TYPE", "NAME", "CRITICAL", "OFFSET"
+        )?;
+        for entry in self.section_index.iter() {
+            writeln!(
+                f,
+                "  {:<6}{:<16}{:<10}{:<12}{}",
+                entry.sh_type,
+                entry.name,
+                if entry.critical { "yes" } else { "no" },
+                entry.sh_offset,
+                entry.sh_size
+            )?;
+        }
+
+        writeln!(f, "\npackage:")?;
+        writeln!(f, "  {:<16}{}", "name", self.package.name)?;
+        writeln!(f, "  {:<16}{}", "version", self.package.version)?;
+        writeln!(f, "  {:<16}{}", "license", self.package.license)?;
+        writeln!(f, "  {:<16}{}", "authors", self.package.authors.join(", "))?;
+        let p = &self.package;
+        for (key, val) in [
+            ("description", p.description.clone()),
+            ("documentation", p.documentation.clone()),
+            ("homepage", p.homepage.clone()),
+            ("repository", p.repository.clone()),
+            ("readme", p.readme.clone()),
+            ("keywords", p.keywords.join(", ")),
+            ("categories", p.categories.join(", ")),
+            ("edition", p.edition.clone()),
+            ("rust-version", p.rust_version.clone()),
+            ("links", p.links.clone()),
+            ("license-file", p.license_file.clone()),
+        ] {
+            if !val.is_empty() {
+                writeln!(f, "  {:<16}{}", key, val)?;
+            }
+        }
+
+        writeln!(f, "\ndependencies ({} entries):", self.dependencies.len())?;
+        writeln!(
+            f,
+            "  {:<24}{:<16}{:<8}{:<12}{:<32}{:<24}OPTIONS",
+            "NAME", "VERSION REQ", "KIND", "SOURCE", "PATH", "PLATFORM"
+        )?;
+        for dep in self.dependencies.iter() {
+            let (src_type, src_path) = match &dep.src {
+                SrcTypePath::CratesIo => ("crates-io", ""),
+                SrcTypePath::Git(path) => ("git", path.as_str()),
+                SrcTypePath::Url(path) => ("url", path.as_str()),
+                SrcTypePath::Registry(path) => ("registry", path.as_str()),
+                SrcTypePath::P2p(path) => ("p2p", path.as_str()),
+            };
+            writeln!(
+                f,
+                "  {:<24}{:<16}{:<8}{:<12}{:<32}{:<24}{}",
+                dep.name,
+                dep.ver_req,
+                dep.kind.name(),
+                src_type,
+                src_path,
+                dep.src_platform,
+                dep_options(dep)
+            )?;
+        }
+
+        if !self.features.is_empty() {
+            writeln!(f, "\nfeatures ({} entries):", self.features.len())?;
+            for (name, items) in self.features.iter() {
+                writeln!(f, "  {:<24}{}", name, items.join(", "))?;
+            }
+        }
+
+        if !self.targets.is_empty() {
+            writeln!(f, "\ntargets ({} entries):", self.targets.len())?;
+            writeln!(f, "  {:<24}{:<24}PATH", "NAME", "KIND")?;
+            for target in self.targets.iter() {
+                writeln!(
+                    f,
+                    "  {:<24}{:<24}{}",
+                    target.name,
+                    target.kind.join(", "),
+                    target.src_path
+                )?;
+            }
+        }
+
+        writeln!(f, "\ncrate binary: {} bytes", self.crate_binary_size)?;
+
+        if let Some(vcs) = &self.vcs {
+            writeln!(f, "\nvcs:")?;
+            writeln!(f, "  {:<16}{}", "vcs", vcs.vcs)?;
+            writeln!(f, "  {:<16}{}", "commit", vcs.commit)?;
+            writeln!(f, "  {:<16}{}", "dirty", vcs.dirty)?;
+            writeln!(f, "  {:<16}{}", "path_in_vcs", vcs.path_in_vcs)?;
+        }
+
+        if !self.locked_packages.is_empty() {
+            writeln!(
+                f,
+                "\nlocked packages ({} entries):",
+                self.locked_packages.len()
+            )?;
+            writeln!(
+                f,
+                "  {:<24}{:<16}{:<56}CHECKSUM",
+                "NAME", "VERSION", "SOURCE"
+            )?;
+            for locked in self.locked_packages.iter() {
+                writeln!(
+                    f,
+                    "  {:<24}{:<16}{:<56}{}",
+                    locked.name, locked.version, locked.source, locked.checksum
+                )?;
+            }
+        }
+
+        writeln!(f, "\nsignatures ({} entries):", self.signatures.len())?;
+        writeln!(f, "  {:<6}{:<12}SIZE", "TYPE", "NAME")?;
+        for sig in self.signatures.iter() {
+            writeln!(
+                f,
+                "  {:<6}{:<12}{}",
+                sig.sigstruct_type, sig.name, sig.sigstruct_size
+            )?;
+        }
+
+        write!(f, "\nfingerprint: {}", self.fingerprint)
+    }
+}
+
+///parse the package structure, nothing is verified or extracted
+pub fn inspect_package(file_path: &str) -> Result<Inspection, Error> {
+    let file = ScrateFile::open(file_path)?;
+    let crate_package = CratePackage::decode_from_slice(file.bytes())?;
+    Inspection::from_crate_package(crate_package)
+}
+
+#[test]
+fn test_inspect() {
+    use crate::utils::context::{PackageContext, SIGTYPE};
+    use crate::utils::pkcs::PKCS;
+
+    let mut pkcs = PKCS::new();
+    pkcs.load_from_file_writer(
+        "test/cert.pem".to_string(),
+        "test/key.pem".to_string(),
+        ["test/root-ca.pem".to_string()].to_vec(),
+    )
+    .unwrap();
+    let mut pack_context = PackageContext::new();
+    pack_context.set_package_info(
+        "rust-crate".to_string(),
+        "1.0.0".to_string(),
+        "MIT".to_string(),
+        vec!["rust".to_string()],
+    );
+    pack_context.add_dep_info(
+        "toml".to_string(),
+        "0.7.4".to_string(),
+        SrcTypePath::CratesIo,
+        "".to_string(),
+    );
+    pack_context.add_crate_bin([15; 100].to_vec());
+    pack_context.vcs_info = Some(VcsInfo {
+        vcs: "git".to_string(),
+        commit: "9de088c".to_string(),
+        dirty: true,
+        path_in_vcs: "crates/rust-crate".to_string(),
+    });
+    pack_context.locked_packages = vec![LockedPackage {
+        name: "toml".to_string(),
+        version: "0.7.4".to_string(),
+        source: "registry+https://github.com/rust-lang/crates.io-index".to_string(),
+        checksum: "ac2caab0bf757388c6c0ae23b3293fdb463fee59434529014f85e3263b995c28".to_string(),
+    }];
+    pack_context.features = BTreeMap::from([(
+        "default".to_string(),
+        vec!["dep:toml".to_string(), "toml/display".to_string()],
+    )]);
+    pack_context.targets = vec![TargetInfo {
+        name: "rust-crate".to_string(),
+        kind: vec!["lib".to_string()],
+        src_path: "src/lib.rs".to_string(),
+    }];
+    pack_context.add_sig(pkcs, SIGTYPE::CRATEBIN);
+    let (_, _, bin) = pack_context.encode_to_crate_package().unwrap();
+
+    let inspection =
+        Inspection::from_crate_package(CratePackage::decode_from_slice(bin.as_slice()).unwrap())
+            .unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(inspection.to_json().unwrap().as_str()).unwrap();
+    assert_eq!(json["magic"], "4352415445");
+    assert_eq!(json["header"]["si_num"], 8);
+    assert_eq!(json["section_index"][2]["name"], "crate-binary");
+    assert_eq!(json["section_index"][2]["sh_size"], 100);
+    assert_eq!(json["package"]["name"], "rust-crate");
+    assert_eq!(json["dependencies"][0]["src"]["type"], "crates-io");
+    assert_eq!(json["crate_binary_size"], 100);
+    assert_eq!(json["section_index"][3]["name"], "vcs-info");
+    assert_eq!(json["section_index"][3]["sh_type"], 0x85);
+    assert_eq!(json["section_index"][3]["critical"], false);
+    assert_eq!(json["vcs"]["commit"], "9de088c");
+    assert_eq!(json["vcs"]["dirty"], true);
+    assert_eq!(json["section_index"][4]["name"], "lockfile");
+    assert_eq!(json["locked_packages"][0]["version"], "0.7.4");
+    assert_eq!(json["section_index"][5]["name"], "features");
+    assert_eq!(json["features"]["default"][1], "toml/display");
+    assert_eq!(json["section_index"][6]["name"], "targets");
+    assert_eq!(json["section_index"][6]["sh_type"], 0x88);
+    assert_eq!(json["targets"][0]["src_path"], "src/lib.rs");
+    assert_eq!(json["signatures"][0]["name"], "crate-bin");
+    assert_eq!(
+        json["fingerprint"].as_str().unwrap(),
+        to_hex(&bin[bin.len() - 32..])
+    );
+}
@@ -1,13 +1,24 @@
-use crate::pack::{pack_context, pack_name};
-use crate::unpack::unpack_context;
-use crate::utils::context::SIGTYPE;
+use crate::inspect::inspect_package;
+use crate::pack::{
+    pack_context, pack_context_from_crate, pack_name, workspace_members, MetadataBackend,
+    PackOptions,
+};
+use crate::sign::{list_sigs, resign_package, sign_package, strip_package, upgrade_package};
+use crate::unpack::{unpack_context, verify_context};
+use crate::utils::context::{sig_type_name, PackageContext, SIGTYPE};
+use crate::utils::error::Error;
+use crate::utils::package::view::ScrateFile;
+use crate::utils::package::CRATEVERSION;
 use crate::utils::pkcs::PKCS;
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 use std::fs;
 use std::path::PathBuf;
+use std::process;
 use std::str::FromStr;
 
+pub mod inspect;
 pub mod pack;
+pub mod sign;
 pub mod unpack;
 pub mod utils;
 
@@ -32,94 +43,626 @@ struct CrateArgs {
 enum CrateCommands {
     Encode(CrateEncodeArgs),
     Decode(CrateDecodeArgs),
+    Verify(CrateVerifyArgs),
+    Inspect(CrateInspectArgs),
+    Sign(CrateSignArgs),
+    StripSigs(CrateStripSigsArgs),
+    Resign(CrateResignArgs),
+    Upgrade(CrateUpgradeArgs),
 }
 
 #[derive(Debug, Args)]
+#[command(group(ArgGroup::new("signing").multiple(true).args(["cert_path", "signer"])))]
 struct CrateEncodeArgs {
     #[arg(short, long, required = false)]
     root_ca_paths: Vec<String>,
-    ///certification file path
-    #[clap(short, long, required = false)]
+    ///certification file path, signs the crate binary
+    #[clap(short, long, required = false, requires = "pkey_path")]
     cert_path: Option<String>,
     ///private key path
-    #[clap(short, long, required = false)]
+    #[clap(short, long, required = false, requires = "cert_path")]
     pkey_path: Option<String>,
+    ///one more signer, <CERT>:<KEY>[:file|crate-bin], crate-bin by default
+    #[clap(short, long, required = false)]
+    signer: Vec<SignerArg>,
+    ///intermediate certs (pem bundle) embedded in every signature
+    #[clap(long, required = false, requires = "signing")]
+    chain: Option<String>,
+    ///where package info and dependencies are read from
+    #[clap(long, value_enum, default_value_t = MetadataBackendArg::Toml)]
+    metadata_backend: MetadataBackendArg,
+    ///encode every publishable member of the workspace INPUT belongs to
+    #[clap(long)]
+    workspace: bool,
+    ///only encode these workspace members
+    #[clap(long = "package", requires = "workspace")]
+    packages: Vec<String>,
+    ///skip these workspace members
+    #[clap(long, requires = "workspace")]
+    exclude: Vec<String>,
+    ///package a working tree with uncommitted changes
+    #[clap(long)]
+    allow_dirty: bool,
+    ///don't build the packaged crate (cargo package --no-verify)
+    #[clap(long)]
+    no_verify: bool,
+    ///features to activate when cargo package builds the crate
+    #[clap(long)]
+    features: Vec<String>,
+    ///cargo target directory, the .crate file is looked up there
+    #[clap(long)]
+    target_dir: Option<PathBuf>,
+    ///require Cargo.lock to be up to date
+    #[clap(long)]
+    locked: bool,
+    ///run cargo without accessing the network
+    #[clap(long)]
+    offline: bool,
+    ///wrap an existing .crate file instead of running cargo package on INPUT
+    #[clap(long, conflicts_with_all = [
+        "input", "workspace", "metadata_backend", "allow_dirty", "no_verify",
+        "features", "target_dir", "locked", "offline",
+    ])]
+    from_crate: Option<String>,
     ///output file path
     #[clap(short, long)]
     output: String,
-    #[clap()]
-    input: String,
+    #[clap(required_unless_present = "from_crate")]
+    input: Option<String>,
+}
+
+impl CrateEncodeArgs {
+    fn pack_options(&self) -> PackOptions {
+        PackOptions {
+            backend: self.metadata_backend.backend(),
+            allow_dirty: self.allow_dirty,
+            no_verify: self.no_verify,
+            features: self.features.clone(),
+            target_dir: self.target_dir.clone(),
+            locked: self.locked,
+            offline: self.offline,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum MetadataBackendArg {
+    ///parse Cargo.toml
+    Toml,
+    ///run `cargo metadata`, follows custom target dirs and workspaces
+    Cargo,
+}
+
+impl MetadataBackendArg {
+    fn backend(self) -> MetadataBackend {
+        match self {
+            MetadataBackendArg::Toml => MetadataBackend::Toml,
+            MetadataBackendArg::Cargo => MetadataBackend::CargoMetadata,
+        }
+    }
 }
 
 #[derive(Debug, Args)]
 struct CrateDecodeArgs {
     #[arg(short, long, required = false)]
     root_ca_paths: Vec<String>,
+    ///extra intermediate certs (pem bundle) used to build the signer chain
+    #[arg(long, required = false)]
+    intermediate: Vec<String>,
     ///output file path
     #[clap(short, long)]
     output: String,
+    ///metadata file format
+    #[clap(short, long, value_enum, default_value_t = MetadataFormat::Json)]
+    metadata_format: MetadataFormat,
+    #[clap()]
+    input: String,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum MetadataFormat {
+    Json,
+    Toml,
+}
+
+#[derive(Debug, Args)]
+struct CrateVerifyArgs {
+    #[arg(short, long, required = false)]
+    root_ca_paths: Vec<String>,
+    ///extra intermediate certs (pem bundle) used to build the signer chain
+    #[arg(long, required = false)]
+    intermediate: Vec<String>,
+    #[clap()]
+    input: String,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Args)]
+struct CrateInspectArgs {
+    ///output format
+    #[clap(short, long, value_enum, default_value_t = OutputFormat::Human)]
+    format: OutputFormat,
+    #[clap()]
+    input: String,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum SignatureType {
+    ///covers the whole package
+    File,
+    ///covers the .crate binary only
+    CrateBin,
+}
+
+impl SignatureType {
+    fn sig_type(self) -> SIGTYPE {
+        match self {
+            SignatureType::File => SIGTYPE::FILE,
+            SignatureType::CrateBin => SIGTYPE::CRATEBIN,
+        }
+    }
+}
+
+///`cert.pem:key.pem[:file|crate-bin]`
+#[derive(Debug, Clone)]
+struct SignerArg {
+    cert_path: String,
+    pkey_path: String,
+    sig_type: SignatureType,
+}
+
+impl FromStr for SignerArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        //the scope is parsed from the end and a drive letter is skipped,
+        //so windows paths like `C:\certs\cert.pem` keep their colons
+        let (paths, sig_type) = match s.rsplit_once(':') {
+            Some((paths, scope))
+                if (scope == "file" || scope == "crate-bin") && split_cert_key(paths).is_some() =>
+            {
+                (paths, SignatureType::from_str(scope, false)?)
+            }
+            _ => (s, SignatureType::CrateBin),
+        };
+        let (cert_path, pkey_path) = split_cert_key(paths)
+            .ok_or_else(|| "expected <CERT>:<KEY>[:file|crate-bin]".to_string())?;
+        if cert_path.is_empty() || pkey_path.is_empty() {
+            return Err("certification and private key path must not be empty".to_string());
+        }
+        Ok(Self {
+            cert_path: cert_path.to_string(),
+            pkey_path: pkey_path.to_string(),
+            sig_type,
+        })
+    }
+}
+
+///split `cert:key` at the colon between them, a windows drive letter's colon
+///in either path doesn't count
+fn split_cert_key(s: &str) -> Option<(&str, &str)> {
+    let (cert_path, pkey_path) = s.split_at(drive_len(s) + s[drive_len(s)..].find(':')?);
+    let pkey_path = &pkey_path[1..];
+    match pkey_path[drive_len(pkey_path)..].contains(':') {
+        true => None,
+        false => Some((cert_path, pkey_path)),
+    }
+}
+
+///length of a leading `C:\` style drive prefix without its separator
+fn drive_len(path: &str) -> usize {
+    match path.as_bytes() {
+        [letter, b':', b'\\' | b'/', ..] if letter.is_ascii_alphabetic() => 2,
+        _ => 0,
+    }
+}
+
+impl SignerArg {
+    ///load the certificate and key, with the `--chain` intermediates if any
+    fn load(&self, root_ca_paths: &[String], chain: &Option<String>) -> Result<PKCS, Error> {
+        let mut pkcs = PKCS::new();
+        pkcs.load_from_file_writer(
+            self.cert_path.clone(),
+            self.pkey_path.clone(),
+            root_ca_paths.to_vec(),
+        )?;
+        if let Some(chain_path) = chain {
+            pkcs.load_chain_from_file(chain_path.clone())?;
+        }
+        Ok(pkcs)
+    }
+}
+
+#[derive(Debug, Args)]
+struct CrateSignArgs {
+    ///root CAs the existing signatures are verified against
+    #[arg(short, long, required = false)]
+    root_ca_paths: Vec<String>,
+    ///extra intermediate certs (pem bundle) used to build the signer chain
+    #[arg(long, required = false)]
+    intermediate: Vec<String>,
+    ///certification file path
+    #[clap(short, long)]
+    cert: String,
+    ///private key path
+    #[clap(short, long)]
+    key: String,
+    ///intermediate certs (pem bundle) embedded in the signature
+    #[clap(long, required = false)]
+    chain: Option<String>,
+    ///signature type
+    #[clap(short = 't', long = "type", value_enum, default_value_t = SignatureType::CrateBin)]
+    sig_type: SignatureType,
+    ///output file path, the input is overwritten if not given
+    #[clap(short, long)]
+    output: Option<String>,
+    #[clap()]
+    input: String,
+}
+
+#[derive(Debug, Args)]
+struct CrateStripSigsArgs {
+    ///list the signatures by index and signer subject, nothing is changed
+    #[clap(short, long, conflicts_with = "index")]
+    list: bool,
+    ///index of a signature to drop, may be repeated
+    #[clap(short, long, required_unless_present = "list")]
+    index: Vec<usize>,
+    ///output file path, the input is overwritten if not given
+    #[clap(short, long, conflicts_with = "list")]
+    output: Option<String>,
+    #[clap()]
+    input: String,
+}
+
+#[derive(Debug, Args)]
+struct CrateResignArgs {
+    ///root CAs the remaining signatures are verified against
+    #[arg(short, long, required = false)]
+    root_ca_paths: Vec<String>,
+    ///extra intermediate certs (pem bundle) used to build the signer chain
+    #[arg(long, required = false)]
+    intermediate: Vec<String>,
+    ///index of a signature to replace, may be repeated
+    #[clap(short, long, required = true)]
+    index: Vec<usize>,
+    ///certification file path
+    #[clap(short, long)]
+    cert: String,
+    ///private key path
+    #[clap(short, long)]
+    key: String,
+    ///intermediate certs (pem bundle) embedded in the signature
+    #[clap(long, required = false)]
+    chain: Option<String>,
+    ///signature type
+    #[clap(short = 't', long = "type", value_enum, default_value_t = SignatureType::CrateBin)]
+    sig_type: SignatureType,
+    ///output file path, the input is overwritten if not given
+    #[clap(short, long)]
+    output: Option<String>,
+    #[clap()]
+    input: String,
+}
+
+#[derive(Debug, Args)]
+struct CrateUpgradeArgs {
+    ///root CAs the existing signatures are verified against
+    #[arg(short, long, required = false)]
+    root_ca_paths: Vec<String>,
+    ///extra intermediate certs (pem bundle) used to build the signer chain
+    #[arg(long, required = false)]
+    intermediate: Vec<String>,
+    ///signs the upgraded package, <CERT>:<KEY>[:file|crate-bin], crate-bin by default
+    #[clap(short, long, required = false)]
+    signer: Vec<SignerArg>,
+    ///intermediate certs (pem bundle) embedded in every new signature
+    #[clap(long, required = false, requires = "signer")]
+    chain: Option<String>,
+    ///output file path, the input is overwritten if not given
+    #[clap(short, long)]
+    output: Option<String>,
     #[clap()]
     input: String,
 }
 
+///exit code of a failed command, one per failure class
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::BadMagic => 2,
+        Error::Format(_) | Error::UnsupportedVersion(_) => 3,
+        Error::FingerprintMismatch => 4,
+        Error::SignatureMismatch(_) => 5,
+        Error::UntrustedSigner(_) => 6,
+        _ => 1,
+    }
+}
+
+fn encode(en_args: CrateEncodeArgs) -> Result<(), Error> {
+    match (&en_args.from_crate, &en_args.input) {
+        (Some(crate_path), _) => {
+            encode_package(pack_context_from_crate(crate_path)?, &en_args)?;
+        }
+        (None, Some(input)) if en_args.workspace => encode_workspace(input, &en_args)?,
+        (None, Some(input)) => {
+            let pack_context = pack_context(input, &en_args.pack_options())?;
+            encode_package(pack_context, &en_args)?;
+        }
+        //clap requires one of them
+        (None, None) => unreachable!(),
+    }
+    Ok(())
+}
+
+///sign and encode the packed package, returns the file written
+fn encode_package(
+    mut pack_context: PackageContext,
+    en_args: &CrateEncodeArgs,
+) -> Result<PathBuf, Error> {
+    //sign package, -c/-p is a crate-bin signer
+    let mut signers = en_args.signer.clone();
+    if let (Some(cert_path), Some(pkey_path)) = (&en_args.cert_path, &en_args.pkey_path) {
+        signers.insert(
+            0,
+            SignerArg {
+                cert_path: cert_path.clone(),
+                pkey_path: pkey_path.clone(),
+                sig_type: SignatureType::CrateBin,
+            },
+        );
+    }
+    for signer in signers {
+        let pkcs = signer.load(&en_args.root_ca_paths, &en_args.chain)?;
+        pack_context.add_sig(pkcs, signer.sig_type.sig_type());
+    }
+
+    //encode package to binary
+    let (_, _, bin) = pack_context.encode_to_crate_package()?;
+
+    //dump binary path/<name>.scrate
+    let mut bin_path = PathBuf::from(en_args.output.as_str());
+    bin_path.push(pack_name(&pack_context));
+    fs::write(&bin_path, bin)?;
+    Ok(bin_path)
+}
+
+///encode the selected publishable members one by one, a failed member doesn't stop the others
+fn encode_workspace(input: &str, en_args: &CrateEncodeArgs) -> Result<(), Error> {
+    let members = workspace_members(input)?;
+    if let Some(name) = en_args
+        .packages
+        .iter()
+        .chain(en_args.exclude.iter())
+        .find(|name| !members.iter().any(|member| &member.name == *name))
+    {
+        return Err(Error::Argument(format!(
+            "`{}` is not a member of the workspace",
+            name
+        )));
+    }
+
+    let mut total = 0;
+    let mut failed = 0;
+    for member in members.iter() {
+        if (!en_args.packages.is_empty() && !en_args.packages.contains(&member.name))
+            || en_args.exclude.contains(&member.name)
+        {
+            continue;
+        }
+        if !member.publish {
+            println!("skipped {}: publish = false", member.name);
+            continue;
+        }
+        total += 1;
+        let packed = pack_context(
+            member.manifest_dir.to_string_lossy().as_ref(),
+            &en_args.pack_options(),
+        );
+        match packed.and_then(|pack_context| encode_package(pack_context, en_args)) {
+            Ok(bin_path) => println!("ok      {}: {}", member.name, bin_path.display()),
+            Err(err) => {
+                failed += 1;
+                eprintln!("failed  {}: {}", member.name, err);
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(Error::Batch(failed, total));
+    }
+    Ok(())
+}
+
+fn decode(de_args: CrateDecodeArgs) -> Result<(), Error> {
+    //decode package from binary
+    let file = ScrateFile::open(de_args.input.as_str())?;
+    let pack_context = unpack_context(file.bytes(), de_args.root_ca_paths, de_args.intermediate)?;
+
+    //extract crate bin file
+    let mut bin_path = PathBuf::from(de_args.output.as_str());
+    bin_path.push(format!(
+        "{}-{}.crate",
+        pack_context.pack_info.name, pack_context.pack_info.version
+    ));
+    fs::write(bin_path, &pack_context.crate_binary.bytes)?;
+
+    //dump scrate metadata
+    let metadata = pack_context.metadata();
+    let (ext, content) = match de_args.metadata_format {
+        MetadataFormat::Json => ("json", metadata.to_json()?),
+        MetadataFormat::Toml => ("toml", metadata.to_toml()?),
+    };
+    let mut metadata_path = PathBuf::from(de_args.output.as_str());
+    metadata_path.push(format!(
+        "{}-{}-metadata.{}",
+        pack_context.pack_info.name, pack_context.pack_info.version, ext
+    ));
+    fs::write(metadata_path, content)?;
+    Ok(())
+}
+
+fn verify(ve_args: CrateVerifyArgs) -> Result<(), Error> {
+    //decode and verify package, nothing is written
+    let file = ScrateFile::open(ve_args.input.as_str())?;
+    let pack_context = verify_context(file.bytes(), ve_args.root_ca_paths, ve_args.intermediate)?;
+    println!(
+        "{}-{}: fingerprint and {} signature(s) verified",
+        pack_context.pack_info.name,
+        pack_context.pack_info.version,
+        pack_context.sig_num()
+    );
+    //a signature section may carry several signers, list them under it
+    let signers = pack_context.metadata().signers;
+    for (i, sig) in pack_context.sigs.iter().enumerate() {
+        println!("\nsignature {} ({}):", i, sig_type_name(sig.typ));
+        for (j, signer) in signers.iter().filter(|s| s.signature == i).enumerate() {
+            let cert = &signer.signer;
+            println!("  signer {}:", j);
+            println!("    {:<16}{}", "subject", cert.subject);
+            println!("    {:<16}{}", "issuer", cert.issuer);
+            println!("    {:<16}{}", "serial", cert.serial);
+            println!("    {:<16}{}", "not before", cert.not_before);
+            println!("    {:<16}{}", "not after", cert.not_after);
+            println!("    {:<16}{}", "sha256", cert.sha256_fingerprint);
+            println!("    {:<16}{}", "cert digest", cert.cert_signature_digest);
+            println!("    {:<16}{}", "root", cert.root);
+        }
+    }
+    Ok(())
+}
+
+fn inspect(in_args: CrateInspectArgs) -> Result<(), Error> {
+    //dump package structure, nothing is verified
+    let inspection = inspect_package(in_args.input.as_str())?;
+    match in_args.format {
+        OutputFormat::Human => println!("{}", inspection),
+        OutputFormat::Json => println!("{}", inspection.to_json()?),
+    }
+    Ok(())
+}
+
+fn sign(si_args: CrateSignArgs) -> Result<(), Error> {
+    let mut pkcs = PKCS::new();
+    pkcs.load_from_file_writer(si_args.cert, si_args.key, si_args.root_ca_paths.clone())?;
+    if let Some(chain_path) = si_args.chain {
+        pkcs.load_chain_from_file(chain_path)?;
+    }
+
+    //existing signatures are verified before one more is added
+    let bin = sign_package(
+        si_args.input.as_str(),
+        si_args.root_ca_paths,
+        si_args.intermediate,
+        pkcs,
+        si_args.sig_type.sig_type(),
+    )?;
+    fs::write(si_args.output.unwrap_or(si_args.input), bin)?;
+    Ok(())
+}
+
+fn strip_sigs(st_args: CrateStripSigsArgs) -> Result<(), Error> {
+    if st_args.list {
+        println!("{:<6}{:<12}SUBJECT", "INDEX", "TYPE");
+        for sig in list_sigs(st_args.input.as_str())? {
+            println!(
+                "{:<6}{:<12}{}",
+                sig.index,
+                sig.sig_type,
+                sig.subjects.join("; ")
+            );
+        }
+        return Ok(());
+    }
+    let bin = strip_package(st_args.input.as_str(), &st_args.index)?;
+    fs::write(st_args.output.unwrap_or(st_args.input), bin)?;
+    Ok(())
+}
+
+fn resign(re_args: CrateResignArgs) -> Result<(), Error> {
+    let mut pkcs = PKCS::new();
+    pkcs.load_from_file_writer(re_args.cert, re_args.key, re_args.root_ca_paths.clone())?;
+    if let Some(chain_path) = re_args.chain {
+        pkcs.load_chain_from_file(chain_path)?;
+    }
+
+    //the replaced signatures are dropped unverified, the remaining ones are verified
+    let bin = resign_package(
+        re_args.input.as_str(),
+        re_args.root_ca_paths,
+        re_args.intermediate,
+        &re_args.index,
+        pkcs,
+        re_args.sig_type.sig_type(),
+    )?;
+    fs::write(re_args.output.unwrap_or(re_args.input), bin)?;
+    Ok(())
+}
+
+fn upgrade(up_args: CrateUpgradeArgs) -> Result<(), Error> {
+    let mut signers = vec![];
+    for signer in up_args.signer.iter() {
+        let pkcs = signer.load(&up_args.root_ca_paths, &up_args.chain)?;
+        signers.push((pkcs, signer.sig_type.sig_type()));
+    }
+
+    //existing signatures are verified before anything is rewritten
+    match upgrade_package(
+        up_args.input.as_str(),
+        up_args.root_ca_paths,
+        up_args.intermediate,
+        signers,
+    )? {
+        Some(bin) => fs::write(up_args.output.unwrap_or(up_args.input), bin)?,
+        None => println!(
+            "{} is already at format version {}",
+            up_args.input, CRATEVERSION
+        ),
+    }
+    Ok(())
+}
+
 fn main() {
     let CargoCli::Crate(args) = CargoCli::parse();
     if let Some(subcommand) = args.command {
-        match subcommand {
-            CrateCommands::Encode(en_args) => {
-                //pack package
-                let mut pack_context = pack_context(&en_args.input);
-
-                //sign package
-                let mut pkcs = PKCS::new();
-                pkcs.load_from_file_writer(
-                    en_args.cert_path.unwrap(),
-                    en_args.pkey_path.unwrap(),
-                    en_args.root_ca_paths,
-                );
-                pack_context.add_sig(pkcs, SIGTYPE::CRATEBIN);
-
-                //encode package to binary
-                let (_, _, bin) = pack_context.encode_to_crate_package();
-
-                //dump binary path/<name>.scrate
-                let mut bin_path = PathBuf::from_str(en_args.output.as_str()).unwrap();
-                bin_path.push(pack_name(&pack_context));
-                fs::write(bin_path, bin).unwrap();
-            }
-            CrateCommands::Decode(de_args) => {
-                //decode package from binary
-                let pack_context = unpack_context(de_args.input.as_str(), de_args.root_ca_paths);
-                if pack_context.is_err() {
-                    eprintln!("{}", pack_context.unwrap_err());
-                    return;
-                }
-                let pack_context = pack_context.unwrap();
-                //extract crate bin file
-                let mut bin_path = PathBuf::from_str(de_args.output.as_str()).unwrap();
-                bin_path.push(format!(
-                    "{}-{}.crate",
-                    pack_context.pack_info.name, pack_context.pack_info.version
-                ));
-                fs::write(bin_path, pack_context.crate_binary.bytes).unwrap();
-
-                //dump scrate metadata
-                let mut metadata_path = PathBuf::from_str(de_args.output.as_str()).unwrap();
-                metadata_path.push(format!(
-                    "{}-{}-metadata.txt",
-                    pack_context.pack_info.name, pack_context.pack_info.version
-                ));
-                fs::write(
-                    metadata_path,
-                    format!(
-                        "{:#?}\n{:#?}",
-                        pack_context.pack_info, pack_context.dep_infos
-                    ),
-                )
-                .unwrap();
-            }
+        let res = match subcommand {
+            CrateCommands::Encode(en_args) => encode(en_args),
+            CrateCommands::Decode(de_args) => decode(de_args),
+            CrateCommands::Verify(ve_args) => verify(ve_args),
+            CrateCommands::Inspect(in_args) => inspect(in_args),
+            CrateCommands::Sign(si_args) => sign(si_args),
+            CrateCommands::StripSigs(st_args) => strip_sigs(st_args),
+            CrateCommands::Resign(re_args) => resign(re_args),
+            CrateCommands::Upgrade(up_args) => upgrade(up_args),
+        };
+        if let Err(err) = res {
+            eprintln!("{}", err);
+            process::exit(exit_code(&err));
         }
     } else {
         println!("Cargo subcommands: {:?}", args.command);
     }
 }
+
+#[test]
+fn test_signer_arg() {
+    let signer = SignerArg::from_str("cert.pem:key.pem").unwrap();
+    assert_eq!(signer.cert_path, "cert.pem");
+    assert_eq!(signer.pkey_path, "key.pem");
+    assert!(matches!(signer.sig_type, SignatureType::CrateBin));
+    let signer = SignerArg::from_str("cert.pem:key.pem:file").unwrap();
+    assert!(matches!(signer.sig_type, SignatureType::File));
+    assert!(SignerArg::from_str("cert.pem").is_err());
+    assert!(SignerArg::from_str("cert.pem:key.pem:all").is_err());
+    assert!(SignerArg::from_str(":key.pem").is_err());
+    let signer = SignerArg::from_str(r"C:\certs\cert.pem:D:\keys\key.pem:file").unwrap();
+    assert_eq!(signer.cert_path, r"C:\certs\cert.pem");
+    assert_eq!(signer.pkey_path, r"D:\keys\key.pem");
+    assert!(matches!(signer.sig_type, SignatureType::File));
+    let signer = SignerArg::from_str(r"cert.pem:C:\key.pem").unwrap();
+    assert_eq!(signer.pkey_path, r"C:\key.pem");
+    assert!(matches!(signer.sig_type, SignatureType::CrateBin));
+}
@@ -1,11 +1,17 @@
-use crate::utils::context::PackageContext;
+use crate::utils::context::{LockedPackage, PackageContext, Skipped, VcsInfo};
+use crate::utils::error::Error;
+use crate::utils::from_lock::CrateLock;
+use crate::utils::from_metadata::{self, CrateMetadata, WorkspaceMember};
 use crate::utils::from_toml::CrateToml;
+use flate2::read::GzDecoder;
+use serde::Deserialize;
 use std::fs;
+use std::io::Read;
 use std::path::PathBuf;
 use std::process::Command;
-use std::str::FromStr;
+use tar::Archive;
 
-fn run_cmd(cmd: &str, args: Vec<&str>, cur_dir: Option<&PathBuf>) -> Result<String, String> {
+fn run_cmd(cmd: &str, args: Vec<&str>, cur_dir: Option<&PathBuf>) -> Result<String, Error> {
     let mut output = Command::new(cmd);
     if !args.is_empty() {
         output.args(args);
@@ -15,76 +21,413 @@ fn run_cmd(cmd: &str, args: Vec<&str>, cur_dir: Option<&PathBuf>) -> Result<Stri
     }
     let output = output
         .output()
-        .unwrap_or_else(|_| panic!("error run cmd {}", cmd));
-    return if output.status.success() {
+        .map_err(|e| Error::Cargo(format!("error run cmd {}: {}", cmd, e)))?;
+    if output.status.success() {
         let stdout = String::from_utf8_lossy(&output.stdout);
         Ok(stdout.to_string())
     } else {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        Err(stderr.to_string())
-    };
+        Err(Error::Cargo(stderr.to_string()))
+    }
+}
+
+///where package info and dependencies are read from
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum MetadataBackend {
+    ///parse Cargo.toml
+    #[default]
+    Toml,
+    ///`cargo metadata`, resolved by cargo itself
+    CargoMetadata,
+}
+
+///how the package is read and built, most of it is passed on to `cargo package`
+#[derive(Debug, Clone, Default)]
+pub struct PackOptions {
+    pub backend: MetadataBackend,
+    ///package a working tree with uncommitted changes, cargo refuses it otherwise
+    pub allow_dirty: bool,
+    ///don't build the packaged crate
+    pub no_verify: bool,
+    pub features: Vec<String>,
+    pub target_dir: Option<PathBuf>,
+    pub locked: bool,
+    pub offline: bool,
 }
 
 struct Packing {
-    pack_context: PackageContext,
+    pack_context: PackageContext<'static>,
     crate_path: PathBuf,
+    options: PackOptions,
 }
 
 impl Packing {
-    fn new(crate_path: &str) -> Self {
+    fn new(crate_path: &str, options: PackOptions) -> Self {
         Packing {
             pack_context: PackageContext::new(),
-            crate_path: PathBuf::from_str(crate_path).unwrap(),
+            crate_path: PathBuf::from(crate_path),
+            options,
         }
     }
 
-    fn cmd_cargo_package(&self) {
-        let res = run_cmd(
+    fn cmd_cargo_package(&self) -> Result<(), Error> {
+        let options = &self.options;
+        let mut args = vec!["package".to_string()];
+        for (flag, set) in [
+            ("--allow-dirty", options.allow_dirty),
+            ("--no-verify", options.no_verify),
+            ("--locked", options.locked),
+            ("--offline", options.offline),
+        ] {
+            if set {
+                args.push(flag.to_string());
+            }
+        }
+        for feature in options.features.iter() {
+            args.push("--features".to_string());
+            args.push(feature.clone());
+        }
+        if let Some(target_dir) = &options.target_dir {
+            args.push("--target-dir".to_string());
+            args.push(target_dir.to_string_lossy().to_string());
+        }
+        let stdout = run_cmd(
             "cargo",
-            ["package", "--allow-dirty"].to_vec(),
+            args.iter().map(String::as_str).collect(),
             Some(&self.crate_path),
-        );
-        if res.is_err() {
-            panic!("{}", res.unwrap_err());
-        } else {
-            println!("{}", res.unwrap());
+        )?;
+        println!("{}", stdout);
+        Ok(())
+    }
+
+    ///whether the packaged directory has uncommitted changes. cargo only packages
+    ///a dirty tree with `--allow-dirty`, and only then git is asked
+    fn is_dirty(&self) -> bool {
+        self.options.allow_dirty
+            && run_cmd(
+                "git",
+                ["status", "--porcelain", "--", "."].to_vec(),
+                Some(&self.crate_path),
+            )
+            .is_ok_and(|status| !status.trim().is_empty())
+    }
+
+    ///Cargo.lock of the package or of the workspace it belongs to
+    fn find_lockfile(&self) -> Result<Option<Vec<u8>>, Error> {
+        let crate_path = fs::canonicalize(&self.crate_path)?;
+        for dir in crate_path.ancestors() {
+            let lockfile = dir.join("Cargo.lock");
+            if lockfile.is_file() {
+                return Ok(Some(fs::read(lockfile)?));
+            }
+        }
+        Ok(None)
+    }
+
+    ///`cargo metadata` of the package, `--no-deps` leaves the dependency graph out
+    fn cargo_metadata(&self, no_deps: bool) -> Result<CrateMetadata, Error> {
+        let toml_path = fs::canonicalize(self.crate_path.join("Cargo.toml"))?;
+        let mut args = ["metadata", "--format-version", "1", "--offline"].to_vec();
+        if no_deps {
+            args.push("--no-deps");
         }
+        let json = run_cmd("cargo", args, Some(&self.crate_path))?;
+        CrateMetadata::from_json(json.as_str(), toml_path.as_path())
     }
 
-    fn read_crate(&mut self) {
-        //parse crate toml file
+    ///fill package info and dependencies, returns the directory cargo packages into
+    fn read_toml(&mut self) -> Result<PathBuf, Error> {
         let mut toml_path = self.crate_path.clone();
         toml_path.push("Cargo.toml");
-        let toml_path = fs::canonicalize(toml_path).unwrap();
-        let toml = CrateToml::from_file(toml_path.to_str().unwrap().to_string());
-        toml.write_info_to_package_context(&mut self.pack_context);
+        let toml_path = fs::canonicalize(toml_path)?;
+        let toml = CrateToml::from_file(toml_path.to_string_lossy().to_string())?;
+        warn_skipped_deps(toml.write_info_to_package_context(&mut self.pack_context)?);
+        //only cargo knows the target dir, CARGO_TARGET_DIR and build.target-dir included
+        Ok(self
+            .cargo_metadata(true)?
+            .target_directory()
+            .join("package"))
+    }
+
+    ///fill package info and dependencies, returns the directory cargo packages into
+    fn read_cargo_metadata(&mut self) -> Result<PathBuf, Error> {
+        let metadata = self.cargo_metadata(false)?;
+        warn_skipped_deps(metadata.write_info_to_package_context(&mut self.pack_context)?);
+        Ok(metadata.target_directory().join("package"))
+    }
+
+    fn read_crate(&mut self) -> Result<(), Error> {
+        let package_dir = match self.options.backend {
+            MetadataBackend::Toml => self.read_toml()?,
+            MetadataBackend::CargoMetadata => self.read_cargo_metadata()?,
+        };
+        //--target-dir wins over what cargo would pick on its own
+        let package_dir = match &self.options.target_dir {
+            Some(target_dir) => target_dir.join("package"),
+            None => package_dir,
+        };
 
         //read crate binary
         let crate_bin_file = format!(
             "{}-{}.crate",
             self.pack_context.pack_info.name, self.pack_context.pack_info.version
         );
-        let mut crate_bin_path = self.crate_path.clone();
-        crate_bin_path.push(format!("target/package/{}", crate_bin_file));
-        let crate_bin_path = fs::canonicalize(crate_bin_path).unwrap();
-        assert!(crate_bin_path.exists());
-        let bin = fs::read(crate_bin_path).unwrap();
+        let bin = fs::read(package_dir.join(crate_bin_file))?;
+        let files = read_crate_files(bin.as_slice())?;
+
+        //commit and path as cargo recorded them, absent outside a git repository
+        if let Some(vcs_info_bin) = files.vcs_info {
+            let mut vcs_info = parse_cargo_vcs_info(vcs_info_bin.as_slice())?;
+            vcs_info.dirty |= self.is_dirty();
+            self.pack_context.vcs_info = Some(vcs_info);
+        }
+
+        //the lockfile cargo verified the package with, else the workspace's
+        let lockfile = match files.lockfile {
+            Some(lockfile) => Some(lockfile),
+            None => self.find_lockfile()?,
+        };
+        if let Some(lockfile) = lockfile {
+            self.pack_context.locked_packages = locked_packages(&self.pack_context, lockfile)?;
+        }
 
         //write to pack_context
         self.pack_context.add_crate_bin(bin);
+        Ok(())
     }
 
-    fn pack_context(mut self) -> PackageContext {
-        self.cmd_cargo_package();
-        self.read_crate();
-        self.pack_context
+    fn pack_context(mut self) -> Result<PackageContext<'static>, Error> {
+        self.cmd_cargo_package()?;
+        self.read_crate()?;
+        Ok(self.pack_context)
     }
 }
 
-pub fn pack_context(path: &str) -> PackageContext {
-    Packing::new(path).pack_context()
+pub fn pack_context(path: &str, options: &PackOptions) -> Result<PackageContext<'static>, Error> {
+    let mut options = options.clone();
+    //cargo runs in the package directory, keep a relative target dir relative to ours
+    if let Some(target_dir) = &options.target_dir {
+        options.target_dir = Some(std::path::absolute(target_dir)?);
+    }
+    Packing::new(path, options).pack_context()
+}
+
+///members of the workspace `path` belongs to
+pub fn workspace_members(path: &str) -> Result<Vec<WorkspaceMember>, Error> {
+    let json = run_cmd(
+        "cargo",
+        [
+            "metadata",
+            "--format-version",
+            "1",
+            "--offline",
+            "--no-deps",
+        ]
+        .to_vec(),
+        Some(&PathBuf::from(path)),
+    )?;
+    from_metadata::workspace_members(json.as_str())
+}
+
+fn warn_skipped_deps(skipped: Vec<Skipped>) {
+    for skipped in skipped {
+        match skipped {
+            Skipped::Dep(dep) => eprintln!(
+                "warning: path-only dev-dependency `{}` is not recorded, cargo strips it when packaging",
+                dep
+            ),
+            Skipped::DepKey(dep, key) => eprintln!(
+                "warning: dependency `{}`: `{}` is not recorded, the dependency table can't represent it",
+                dep, key
+            ),
+        }
+    }
+}
+
+///wrap an existing `.crate` file, package info and dependencies come from the
+///normalized Cargo.toml inside it, cargo is not needed
+pub fn pack_context_from_crate(crate_path: &str) -> Result<PackageContext<'static>, Error> {
+    let bin = fs::read(crate_path)?;
+    let files = read_crate_files(bin.as_slice())?;
+    let toml = CrateToml::from_vec(files.manifest)?;
+    let mut pack_context = PackageContext::new();
+    if let Some(vcs_info_bin) = files.vcs_info {
+        pack_context.vcs_info = Some(parse_cargo_vcs_info(vcs_info_bin.as_slice())?);
+    }
+    warn_skipped_deps(toml.write_info_to_package_context(&mut pack_context)?);
+    let expected_dir = format!(
+        "{}-{}",
+        pack_context.pack_info.name, pack_context.pack_info.version
+    );
+    if files.top_dir != expected_dir {
+        return Err(Error::Manifest(format!(
+            "{} holds `{}`, but its Cargo.toml describes {}",
+            crate_path, files.top_dir, expected_dir
+        )));
+    }
+    if let Some(lockfile) = files.lockfile {
+        pack_context.locked_packages = locked_packages(&pack_context, lockfile)?;
+    }
+    pack_context.add_crate_bin(bin);
+    Ok(pack_context)
+}
+
+///the files of a `.crate` tarball we read
+struct CrateFiles {
+    ///`<name>-<version>`
+    top_dir: String,
+    manifest: Vec<u8>,
+    ///`.cargo_vcs_info.json`, only there if packaged from a git repository
+    vcs_info: Option<Vec<u8>>,
+    ///`Cargo.lock`, recent cargo always packages it
+    lockfile: Option<Vec<u8>>,
+}
+
+fn read_crate_files(bin: &[u8]) -> Result<CrateFiles, Error> {
+    let mut archive = Archive::new(GzDecoder::new(bin));
+    let mut manifest = None;
+    let mut vcs_info = None;
+    let mut lockfile = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        let components: Vec<_> = path.components().collect();
+        if components.len() != 2 {
+            continue;
+        }
+        let top_dir = components[0].as_os_str().to_string_lossy().to_string();
+        let mut content = vec![];
+        if path.ends_with("Cargo.toml") {
+            entry.read_to_end(&mut content)?;
+            manifest = Some((top_dir, content));
+        } else if path.ends_with(".cargo_vcs_info.json") {
+            entry.read_to_end(&mut content)?;
+            vcs_info = Some(content);
+        } else if path.ends_with("Cargo.lock") {
+            entry.read_to_end(&mut content)?;
+            lockfile = Some(content);
+        }
+    }
+    let (top_dir, manifest) = manifest
+        .ok_or_else(|| Error::Manifest("no Cargo.toml found in the .crate file".to_string()))?;
+    Ok(CrateFiles {
+        top_dir,
+        manifest,
+        vcs_info,
+        lockfile,
+    })
+}
+
+///the resolved dependency graph of the packaged crate
+fn locked_packages(
+    pack_context: &PackageContext,
+    lockfile: Vec<u8>,
+) -> Result<Vec<LockedPackage>, Error> {
+    let pack_info = &pack_context.pack_info;
+    CrateLock::from_vec(lockfile)?.resolved_deps(&pack_info.name, &pack_info.version)
+}
+
+///`.cargo_vcs_info.json` written by `cargo package`
+#[derive(Deserialize)]
+struct CargoVcsInfo {
+    git: CargoVcsGit,
+    #[serde(default)]
+    path_in_vcs: String,
+}
+
+#[derive(Deserialize)]
+struct CargoVcsGit {
+    sha1: String,
+    #[serde(default)]
+    dirty: bool,
+}
+
+fn parse_cargo_vcs_info(bin: &[u8]) -> Result<VcsInfo, Error> {
+    let info: CargoVcsInfo = serde_json::from_slice(bin)
+        .map_err(|e| Error::Manifest(format!(".cargo_vcs_info.json not right: {}", e)))?;
+    Ok(VcsInfo {
+        vcs: "git".to_string(),
+        commit: info.git.sha1,
+        dirty: info.git.dirty,
+        path_in_vcs: info.path_in_vcs,
+    })
 }
 
 pub fn pack_name(pack: &PackageContext) -> String {
     format!("{}-{}.scrate", pack.pack_info.name, pack.pack_info.version)
 }
+
+#[test]
+fn test_pack_context_from_crate() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::env;
+
+    fn crate_file(dir: &str, manifest: &str) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+        for (path, content) in [
+            (format!("{}/Cargo.toml", dir), manifest),
+            (format!("{}/Cargo.toml.orig", dir), manifest),
+            (format!("{}/src/lib.rs", dir), ""),
+            (
+                format!("{}/.cargo_vcs_info.json", dir),
+                r#"{"git": {"sha1": "9de088c5"}, "path_in_vcs": "foo"}"#,
+            ),
+            (format!("{}/Cargo.lock", dir), LOCKFILE),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    const LOCKFILE: &str = r#"
+version = 4
+
+[[package]]
+name = "foo"
+version = "1.2.3"
+dependencies = ["serde"]
+
+[[package]]
+name = "serde"
+version = "1.0.200"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "ddc6f9cc94d67c0e21aaf7eda3a010fd3af78ebf6e096aa6e2e13c79749cce4f"
+"#;
+    let manifest = r#"
+[package]
+name = "foo"
+version = "1.2.3"
+license = "MIT"
+
+[dependencies.serde]
+version = "1.0"
+features = ["derive"]
+"#;
+    let path = env::temp_dir().join("cargo-crate-test-foo-1.2.3.crate");
+    let bin = crate_file("foo-1.2.3", manifest);
+    fs::write(&path, &bin).unwrap();
+    let pack_context = pack_context_from_crate(path.to_str().unwrap()).unwrap();
+    assert_eq!(pack_context.pack_info.name, "foo");
+    assert_eq!(pack_context.dep_infos[0].features, ["derive"]);
+    assert_eq!(*pack_context.crate_binary.bytes, bin);
+    let vcs_info = pack_context.vcs_info.unwrap();
+    assert_eq!(vcs_info.commit, "9de088c5");
+    assert!(!vcs_info.dirty);
+    assert_eq!(vcs_info.path_in_vcs, "foo");
+    assert_eq!(pack_context.locked_packages.len(), 1);
+    assert_eq!(pack_context.locked_packages[0].version, "1.0.200");
+
+    fs::write(&path, crate_file("bar-1.2.3", manifest)).unwrap();
+    assert!(matches!(
+        pack_context_from_crate(path.to_str().unwrap()),
+        Err(Error::Manifest(_))
+    ));
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,382 @@
+use crate::utils::context::{sig_type_name, PackageContext, SIGTYPE};
+use crate::utils::error::Error;
+use crate::utils::package::view::ScrateFile;
+use crate::utils::package::{format_version, has_wide_offsets, CratePackage, CRATEVERSION};
+use crate::utils::pkcs::PKCS;
+use std::fs;
+
+///one signature of a package
+#[derive(Debug)]
+pub struct SigListEntry {
+    pub index: usize,
+    pub sig_type: &'static str,
+    pub subjects: Vec<String>,
+}
+
+///read the package in `bin`, its signatures are not verified
+fn read_package(bin: &[u8]) -> Result<(PackageContext<'_>, CratePackage<'_>), Error> {
+    let mut pack_context = PackageContext::new();
+    let (crate_package, _str_table) = pack_context.decode_from_crate_package_unverified(bin)?;
+    Ok((pack_context, crate_package))
+}
+
+///drop the signatures at `indices`
+fn remove_sigs(pack_context: &mut PackageContext, indices: &[usize]) -> Result<(), Error> {
+    if let Some(index) = indices
+        .iter()
+        .find(|index| **index >= pack_context.sig_num())
+    {
+        return Err(Error::Argument(format!(
+            "signature index {} out of range, the package has {} signature(s)",
+            index,
+            pack_context.sig_num()
+        )));
+    }
+    let mut no = 0;
+    pack_context.sigs.retain(|_| {
+        no += 1;
+        !indices.contains(&(no - 1))
+    });
+    Ok(())
+}
+
+///re-encode the package `bin`, the signatures left in `pack_context` are kept byte-for-byte
+fn reencode(
+    pack_context: &mut PackageContext,
+    crate_package: &CratePackage,
+    bin_old: &[u8],
+) -> Result<Vec<u8>, Error> {
+    //file signatures cover the layout they were made over, re-encoding an older
+    //version changes it (and c_version 0 ones also cover the signature count)
+    let c_version = crate_package.crate_header.c_version;
+    let keeps_file_sigs = pack_context
+        .sigs
+        .iter()
+        .any(|sig| sig.typ == SIGTYPE::FILE.typ());
+    if format_version(c_version) != CRATEVERSION && keeps_file_sigs {
+        return Err(Error::Format(format!(
+            "c_version {} file signatures can't be kept, run `cargo crate upgrade` first",
+            format_version(c_version)
+        )));
+    }
+    let (new_package, _, bin) = pack_context.encode_to_crate_package()?;
+    //so does the offset width, which follows the package size
+    if has_wide_offsets(new_package.crate_header.c_version) != has_wide_offsets(c_version)
+        && keeps_file_sigs
+    {
+        return Err(Error::Format(
+            "the package changes offset width, its file signatures can't be kept".to_string(),
+        ));
+    }
+    //the kept file signatures only verify if they cover the very same bytes as before
+    if keeps_file_sigs
+        && pack_context.binary_before_sig(&new_package, &bin)?
+            != pack_context.binary_before_sig(crate_package, bin_old)?
+    {
+        return Err(Error::Format(
+            "re-encoding changes the signed bytes, its file signatures can't be kept".to_string(),
+        ));
+    }
+    Ok(bin)
+}
+
+///list the signatures with their signers, nothing is verified
+pub fn list_sigs(file_path: &str) -> Result<Vec<SigListEntry>, Error> {
+    let file = ScrateFile::open(file_path)?;
+    let (pack_context, _) = read_package(file.bytes())?;
+    Ok(pack_context
+        .sigs
+        .iter()
+        .enumerate()
+        .map(|(index, sig)| SigListEntry {
+            index,
+            sig_type: sig_type_name(sig.typ),
+            subjects: PKCS::signer_certs(sig.bin.as_slice())
+                .into_iter()
+                .map(|signer| signer.subject)
+                .collect(),
+        })
+        .collect())
+}
+
+///drop the signatures at `indices`, package, dependency and crate binary sections are untouched
+pub fn strip_package(file_path: &str, indices: &[usize]) -> Result<Vec<u8>, Error> {
+    let file = ScrateFile::open(file_path)?;
+    let (mut pack_context, crate_package) = read_package(file.bytes())?;
+    remove_sigs(&mut pack_context, indices)?;
+    reencode(&mut pack_context, &crate_package, file.bytes())
+}
+
+///drop the signatures at `indices`, verify the remaining ones, then append a new signature.
+///the dropped signatures are not verified, their signer may no longer be trusted.
+pub fn resign_package(
+    file_path: &str,
+    cas_path: Vec<String>,
+    intermediates_path: Vec<String>,
+    indices: &[usize],
+    pkcs: PKCS,
+    sig_type: SIGTYPE,
+) -> Result<Vec<u8>, Error> {
+    let file = ScrateFile::open(file_path)?;
+    let (mut pack_context, crate_package) = read_package(file.bytes())?;
+    remove_sigs(&mut pack_context, indices)?;
+    pack_context.set_root_cas_bin(PKCS::root_ca_bins(cas_path)?);
+    pack_context.set_intermediate_cas_bin(
+        intermediates_path
+            .iter()
+            .map(fs::read)
+            .collect::<Result<_, _>>()?,
+    );
+    pack_context.check_sigs(&crate_package, file.bytes())?;
+    pack_context.add_sig(pkcs, sig_type);
+    reencode(&mut pack_context, &crate_package, file.bytes())
+}
+
+///verify the package, then append one more signature.
+///existing signatures are kept byte-for-byte and still verify afterwards.
+pub fn sign_package(
+    file_path: &str,
+    cas_path: Vec<String>,
+    intermediates_path: Vec<String>,
+    pkcs: PKCS,
+    sig_type: SIGTYPE,
+) -> Result<Vec<u8>, Error> {
+    resign_package(file_path, cas_path, intermediates_path, &[], pkcs, sig_type)
+}
+
+///rewrite a package of an older format version in the current one. the signatures are
+///verified first; crate-bin ones are kept byte-for-byte, file ones cover the old layout
+///so they are dropped and `signers` sign the upgraded package.
+///None if the package is already at the current version.
+pub fn upgrade_package(
+    file_path: &str,
+    cas_path: Vec<String>,
+    intermediates_path: Vec<String>,
+    signers: Vec<(PKCS, SIGTYPE)>,
+) -> Result<Option<Vec<u8>>, Error> {
+    let file = ScrateFile::open(file_path)?;
+    let (mut pack_context, crate_package) = read_package(file.bytes())?;
+    if format_version(crate_package.crate_header.c_version) == CRATEVERSION {
+        return Ok(None);
+    }
+    pack_context.set_root_cas_bin(PKCS::root_ca_bins(cas_path)?);
+    pack_context.set_intermediate_cas_bin(
+        intermediates_path
+            .iter()
+            .map(fs::read)
+            .collect::<Result<_, _>>()?,
+    );
+    pack_context.check_sigs(&crate_package, file.bytes())?;
+
+    let sig_num = pack_context.sig_num();
+    pack_context
+        .sigs
+        .retain(|sig| sig.typ != SIGTYPE::FILE.typ());
+    if pack_context.sig_num() < sig_num && signers.is_empty() {
+        return Err(Error::Argument(format!(
+            "{} file signature(s) can't be carried over to format version {}, give a signer",
+            sig_num - pack_context.sig_num(),
+            CRATEVERSION
+        )));
+    }
+    for (pkcs, sig_type) in signers {
+        pack_context.add_sig(pkcs, sig_type);
+    }
+    let (_, _, bin) = pack_context.encode_to_crate_package()?;
+    Ok(Some(bin))
+}
+
+#[test]
+fn test_sign() {
+    use crate::unpack::verify_context;
+    use crate::utils::context::PackageInfo;
+
+    fn pkcs() -> PKCS {
+        let mut pkcs = PKCS::new();
+        pkcs.load_from_file_writer(
+            "test/cert.pem".to_string(),
+            "test/key.pem".to_string(),
+            ["test/root-ca.pem".to_string()].to_vec(),
+        )
+        .unwrap();
+        pkcs
+    }
+
+    let mut pack_context = PackageContext::new();
+    pack_context.pack_info = PackageInfo::new(
+        "rust-crate".to_string(),
+        "1.0.0".to_string(),
+        "MIT".to_string(),
+        vec!["rust".to_string()],
+    );
+    pack_context.add_crate_bin([15; 100].to_vec());
+    pack_context.add_sig(pkcs(), SIGTYPE::FILE);
+    pack_context.add_sig(pkcs(), SIGTYPE::CRATEBIN);
+    let (_, _, bin) = pack_context.encode_to_crate_package().unwrap();
+    let path = std::env::temp_dir().join(format!(
+        "cargo-crate-test-sign-{}.scrate",
+        std::process::id()
+    ));
+    let path = path.to_str().unwrap();
+    fs::write(path, bin).unwrap();
+
+    let root_cas = vec!["test/root-ca.pem".to_string()];
+    let signed = sign_package(path, root_cas.clone(), vec![], pkcs(), SIGTYPE::FILE).unwrap();
+    fs::write(path, signed).unwrap();
+    let signed = sign_package(path, root_cas.clone(), vec![], pkcs(), SIGTYPE::CRATEBIN).unwrap();
+    fs::write(path, signed).unwrap();
+
+    let bin = fs::read(path).unwrap();
+    fs::remove_file(path).unwrap();
+    let pack_context_signed = verify_context(&bin, root_cas, vec![]);
+    let pack_context_signed = pack_context_signed.unwrap();
+    assert_eq!(pack_context_signed.sig_num(), 4);
+    for (old, new) in pack_context
+        .sigs
+        .iter()
+        .zip(pack_context_signed.sigs.iter())
+    {
+        assert_eq!(old.bin, new.bin);
+    }
+    assert_eq!(pack_context_signed.pack_info, pack_context.pack_info);
+}
+
+#[test]
+fn test_upgrade() {
+    use crate::unpack::verify_context;
+    use crate::utils::context::PackageInfo;
+    use crate::utils::package::gen_bincode::encode2vec_in;
+    use crate::utils::package::{DataSection, UnknownSection, FINGERPRINT_LEN};
+
+    fn pkcs() -> PKCS {
+        let mut pkcs = PKCS::new();
+        pkcs.load_from_file_writer(
+            "test/cert.pem".to_string(),
+            "test/key.pem".to_string(),
+            ["test/root-ca.pem".to_string()].to_vec(),
+        )
+        .unwrap();
+        pkcs
+    }
+
+    fn fix_fingerprint(bin: &mut [u8]) {
+        let fp_off = bin.len() - FINGERPRINT_LEN;
+        let fp = PKCS::new().gen_digest_256(&bin[..fp_off]).unwrap();
+        bin[fp_off..].copy_from_slice(fp.as_slice());
+    }
+
+    let mut pack_context = PackageContext::new();
+    pack_context.pack_info = PackageInfo::new(
+        "rust-crate".to_string(),
+        "1.0.0".to_string(),
+        "MIT".to_string(),
+        vec!["rust".to_string()],
+    );
+    pack_context.add_crate_bin([15; 100].to_vec());
+    pack_context.add_sig(pkcs(), SIGTYPE::CRATEBIN);
+    let (mut crate_package, _, _) = pack_context.encode_to_crate_package().unwrap();
+
+    //write it as c_version 3: the package section stops after the authors,
+    //the empty dependency table and the signature read the same
+    let mut package_bin = encode2vec_in(
+        &crate_package.data_sections.col.arr[0],
+        crate_package.layout(),
+    );
+    package_bin.truncate(4 * 3 + 4 + 4);
+    crate_package.data_sections.col.arr[0] = DataSection::Unknown(UnknownSection {
+        sh_type: 0,
+        bin: package_bin.into(),
+    });
+    crate_package.crate_header.c_version = 3;
+    crate_package.set_section_index();
+    crate_package.set_crate_header(0).unwrap();
+    let mut bin = crate_package.encode_to_vec();
+    fix_fingerprint(&mut bin);
+    let path = std::env::temp_dir().join(format!(
+        "cargo-crate-test-upgrade-{}.scrate",
+        std::process::id()
+    ));
+    let path = path.to_str().unwrap();
+    fs::write(path, &bin).unwrap();
+
+    let root_cas = vec!["test/root-ca.pem".to_string()];
+    let upgraded = upgrade_package(
+        path,
+        root_cas.clone(),
+        vec![],
+        vec![(pkcs(), SIGTYPE::FILE)],
+    );
+    let upgraded = upgraded.unwrap().unwrap();
+    fs::write(path, &upgraded).unwrap();
+    let already = upgrade_package(path, root_cas.clone(), vec![], vec![]);
+    let upgraded_context = verify_context(&upgraded, root_cas, vec![]);
+
+    //a version from the future is refused with its own error
+    bin[crate::utils::package::MAGIC_NUMBER_LEN] = CRATEVERSION + 1;
+    fix_fingerprint(&mut bin);
+    fs::write(path, &bin).unwrap();
+    let future = list_sigs(path);
+    fs::remove_file(path).unwrap();
+
+    assert!(already.unwrap().is_none());
+    let upgraded_context = upgraded_context.unwrap();
+    assert_eq!(upgraded_context.pack_info, pack_context.pack_info);
+    assert_eq!(upgraded_context.sig_num(), 2);
+    assert_eq!(upgraded_context.sigs[0].bin, pack_context.sigs[0].bin);
+    assert_eq!(upgraded_context.sigs[1].typ, SIGTYPE::FILE.typ());
+    assert!(matches!(future, Err(Error::UnsupportedVersion(v)) if v == CRATEVERSION + 1));
+}
+
+#[test]
+fn test_sign_reordered_str_table() {
+    use crate::unpack::verify_context;
+    use crate::utils::context::{PackageInfo, StringTable};
+    use std::borrow::Cow;
+
+    fn pkcs() -> PKCS {
+        let mut pkcs = PKCS::new();
+        pkcs.load_from_file_writer(
+            "test/cert.pem".to_string(),
+            "test/key.pem".to_string(),
+            ["test/root-ca.pem".to_string()].to_vec(),
+        )
+        .unwrap();
+        pkcs
+    }
+
+    //a package whose string table isn't in the order encoding would write it
+    let mut str_table = StringTable::new();
+    for st in ["MIT", "rust", "1.0.0", "rust-crate", "unused"] {
+        str_table.insert_str(st.to_string()).unwrap();
+    }
+    let mut pack_context = PackageContext::new();
+    pack_context.pack_info = PackageInfo::new(
+        "rust-crate".to_string(),
+        "1.0.0".to_string(),
+        "MIT".to_string(),
+        vec!["rust".to_string()],
+    );
+    pack_context.add_crate_bin([15; 100].to_vec());
+    pack_context.base_str_table = Cow::Owned(str_table.to_bytes());
+    pack_context.add_sig(pkcs(), SIGTYPE::FILE);
+    let (_, _, bin) = pack_context.encode_to_crate_package().unwrap();
+    let path = std::env::temp_dir().join(format!(
+        "cargo-crate-test-sign-reordered-{}.scrate",
+        std::process::id()
+    ));
+    let path = path.to_str().unwrap();
+    fs::write(path, bin).unwrap();
+
+    let root_cas = vec!["test/root-ca.pem".to_string()];
+    let signed = sign_package(path, root_cas.clone(), vec![], pkcs(), SIGTYPE::FILE).unwrap();
+    fs::write(path, &signed).unwrap();
+    let stripped = strip_package(path, &[1]).unwrap();
+    fs::remove_file(path).unwrap();
+
+    let signed_context = verify_context(&signed, root_cas.clone(), vec![]).unwrap();
+    assert_eq!(signed_context.sig_num(), 2);
+    assert_eq!(signed_context.sigs[0].bin, pack_context.sigs[0].bin);
+    let stripped_context = verify_context(&stripped, root_cas, vec![]).unwrap();
+    assert_eq!(stripped_context.sig_num(), 1);
+    assert_eq!(stripped_context.sigs[0].bin, pack_context.sigs[0].bin);
+}
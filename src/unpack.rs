@@ -1,70 +1,123 @@
 use crate::utils::context::PackageContext;
+use crate::utils::error::Error;
+use crate::utils::package::CratePackage;
 use crate::utils::pkcs::PKCS;
-use std::path::PathBuf;
-use std::str::FromStr;
 use std::fs;
+use std::path::PathBuf;
 
 struct Unpacking {
-    file_path: PathBuf,
     cas_path: Vec<String>,
+    intermediates_path: Vec<String>,
 }
 
 impl Unpacking {
-    pub fn new(path: &str) -> Unpacking {
+    pub fn new() -> Unpacking {
         Unpacking {
-            file_path: PathBuf::from_str(path).unwrap(),
             cas_path: Vec::new(),
+            intermediates_path: Vec::new(),
         }
     }
 
-    pub fn add_ca_from_file(&mut self, path: &str) {
-        let file_path = fs::canonicalize(PathBuf::from_str(path).unwrap()).unwrap();
-        self.cas_path.push(file_path.to_str().unwrap().to_string());
+    pub fn add_ca_from_file(&mut self, path: &str) -> Result<(), Error> {
+        let file_path = fs::canonicalize(PathBuf::from(path))?;
+        self.cas_path.push(file_path.to_string_lossy().to_string());
+        Ok(())
     }
 
-    pub fn unpack_context(self) -> Result<PackageContext, String> {
+    pub fn add_intermediate_from_file(&mut self, path: &str) -> Result<(), Error> {
+        let file_path = fs::canonicalize(PathBuf::from(path))?;
+        self.intermediates_path
+            .push(file_path.to_string_lossy().to_string());
+        Ok(())
+    }
+
+    ///`bin` is the whole package, the crate binary is borrowed from it
+    pub fn unpack(self, bin: &[u8]) -> Result<(PackageContext<'_>, CratePackage<'_>), Error> {
         let mut package_context_new = PackageContext::new();
-        package_context_new.set_root_cas_bin(PKCS::root_ca_bins(self.cas_path));
-        let bin = fs::read(self.file_path).unwrap();
-        let (_crate_package_new, _str_table) =
-            package_context_new.decode_from_crate_package(bin.as_slice())?;
-        Ok(package_context_new)
+        package_context_new.set_root_cas_bin(PKCS::root_ca_bins(self.cas_path)?);
+        package_context_new.set_intermediate_cas_bin(
+            self.intermediates_path
+                .iter()
+                .map(fs::read)
+                .collect::<Result<_, _>>()?,
+        );
+        let (crate_package_new, _str_table) = package_context_new.decode_from_crate_package(bin)?;
+        Ok((package_context_new, crate_package_new))
+    }
+}
+
+///decode and verify the package in `bin`, e.g. a mapped `ScrateFile`,
+///the parsed structure is returned as well
+pub fn unpack_package(
+    bin: &[u8],
+    cas_path: Vec<String>,
+    intermediates_path: Vec<String>,
+) -> Result<(PackageContext<'_>, CratePackage<'_>), Error> {
+    let mut unpack = Unpacking::new();
+    for ca_path in cas_path.iter() {
+        unpack.add_ca_from_file(ca_path.as_str())?;
+    }
+    for intermediate_path in intermediates_path.iter() {
+        unpack.add_intermediate_from_file(intermediate_path.as_str())?;
     }
+    unpack.unpack(bin)
 }
 
-pub fn unpack_context(file_path: &str, cas_path: Vec<String>) -> Result<PackageContext, String> {
-    let mut unpack = Unpacking::new(file_path);
-    cas_path
-        .iter()
-        .for_each(|ca_path| unpack.add_ca_from_file(ca_path.as_str()));
-    unpack.unpack_context()
+pub fn unpack_context(
+    bin: &[u8],
+    cas_path: Vec<String>,
+    intermediates_path: Vec<String>,
+) -> Result<PackageContext<'_>, Error> {
+    let (pack_context, _crate_package) = unpack_package(bin, cas_path, intermediates_path)?;
+    Ok(pack_context)
+}
+
+///decode and verify the package without extracting anything,
+///a package that carries no signature is not trusted
+pub fn verify_context(
+    bin: &[u8],
+    cas_path: Vec<String>,
+    intermediates_path: Vec<String>,
+) -> Result<PackageContext<'_>, Error> {
+    let pack_context = unpack_context(bin, cas_path, intermediates_path)?;
+    if pack_context.sig_num() == 0 {
+        return Err(Error::UntrustedSigner("package is not signed".to_string()));
+    }
+    Ok(pack_context)
 }
 
 #[test]
 fn test_unpack() {
     use std::env;
-    
-    use crate::utils::context::SIGTYPE;
-    use crate::pack::pack_context;
 
+    use crate::pack::{pack_context, PackOptions};
+    use crate::utils::context::SIGTYPE;
 
-    let mut pack_context = pack_context(env::current_dir().unwrap().to_str().unwrap());
+    let mut pack_context = pack_context(
+        env::current_dir().unwrap().to_str().unwrap(),
+        &PackOptions {
+            //the test runs inside a working copy that may have local changes
+            allow_dirty: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
     fn sign() -> PKCS {
         let mut pkcs1 = PKCS::new();
-        pkcs1.load_from_file_writer(
-            "test/cert.pem".to_string(),
-            "test/key.pem".to_string(),
-            ["test/root-ca.pem".to_string()].to_vec(),
-        );
+        pkcs1
+            .load_from_file_writer(
+                "test/cert.pem".to_string(),
+                "test/key.pem".to_string(),
+                ["test/root-ca.pem".to_string()].to_vec(),
+            )
+            .unwrap();
         pkcs1
     }
     pack_context.add_sig(sign(), SIGTYPE::CRATEBIN);
 
-    let (_, _, bin) = pack_context.encode_to_crate_package();
-    fs::write(PathBuf::from_str("test/crate-spec.cra").unwrap(), bin).unwrap();
+    let (_, _, bin) = pack_context.encode_to_crate_package().unwrap();
 
-    let pack_context_decode =
-        unpack_context("test/crate-spec.cra", vec!["test/root-ca.pem".to_string()]);
+    let pack_context_decode = unpack_context(&bin, vec!["test/root-ca.pem".to_string()], vec![]);
 
     assert_eq!(
         pack_context_decode.as_ref().unwrap().pack_info,
@@ -78,6 +131,4 @@ fn test_unpack() {
         pack_context_decode.unwrap().crate_binary,
         pack_context.crate_binary
     );
-
-    fs::remove_file("test/crate-spec.cra").unwrap()
 }
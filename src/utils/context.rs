@@ -1,35 +1,85 @@
+use crate::utils::error::Error;
 use crate::utils::package::{
-    CrateBinarySection, DepTableEntry, LenArrayType, PackageSection, RawArrayType,
-    SigStructureSection, Size, Type,
+    CrateBinarySection, DepTableEntry, FeatureEntry, FeaturesSection, LenArrayType, LockfileEntry,
+    PackageSection, RawArrayType, SigStructureSection, Size, TargetEntry, TargetsSection, Type,
+    Uchar, UnknownSection, VcsInfoSection, DEP_FLAG_NO_DEFAULT_FEATURES, DEP_FLAG_OPTIONAL,
 };
-use crate::utils::pkcs::PKCS;
-use std::collections::HashMap;
-
-pub const NOT_SIG_NUM: usize = 3;
+use crate::utils::pkcs::{SignerCert, PKCS};
+use serde::{Deserialize, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 
 pub enum SIGTYPE {
     FILE,
     CRATEBIN,
 }
 
+impl SIGTYPE {
+    pub fn from_typ(typ: u32) -> Option<Self> {
+        match typ {
+            0 => Some(SIGTYPE::FILE),
+            1 => Some(SIGTYPE::CRATEBIN),
+            _ => None,
+        }
+    }
+
+    pub fn typ(&self) -> u32 {
+        match self {
+            SIGTYPE::FILE => 0,
+            SIGTYPE::CRATEBIN => 1,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SIGTYPE::FILE => "file",
+            SIGTYPE::CRATEBIN => "crate-bin",
+        }
+    }
+}
+
+pub fn sig_type_name(typ: u32) -> &'static str {
+    SIGTYPE::from_typ(typ).map_or("unknown", |t| t.name())
+}
+
 pub enum DATASECTIONTYPE {
     PACK = 0,
     DEPTABLE = 1,
     CRATEBIN = 3,
     SIGSTRUCTURE = 4,
+    VCSINFO = 5,
+    LOCKFILE = 6,
+    FEATURES = 7,
+    TARGETS = 8,
 }
 
-///package context contains package's self and dependency package info
+///package context contains package's self and dependency package info,
+///decoded from a file it borrows the crate binary and unknown sections from it
 #[derive(Debug, PartialEq)]
-pub struct PackageContext {
+pub struct PackageContext<'a> {
     pub pack_info: PackageInfo,
     pub dep_infos: Vec<DepInfo>,
-    pub crate_binary: CrateBinary,
+    pub crate_binary: CrateBinary<'a>,
     pub sigs: Vec<SigInfo>,
     pub root_cas: Vec<Vec<u8>>,
+    ///untrusted intermediate certs used to build the signers' chains
+    pub intermediate_cas: Vec<Vec<u8>>,
+    ///where the source was packaged from, if known
+    pub vcs_info: Option<VcsInfo>,
+    ///resolved dependency graph from Cargo.lock, empty if there was none
+    pub locked_packages: Vec<LockedPackage>,
+    ///`[features]`, including the implicit features of optional dependencies
+    pub features: BTreeMap<String, Vec<String>>,
+    ///lib and bin targets, empty if the metadata backend doesn't know them
+    pub targets: Vec<TargetInfo>,
+    ///non-critical sections of types this version doesn't know, written back as they are
+    pub unknown_sections: Vec<UnknownSection<'a>>,
+    ///string table of the decoded package, encoding starts from it so the strings
+    ///keep their offsets, unknown sections may point into it
+    pub base_str_table: Cow<'a, [u8]>,
 }
 
-impl PackageContext {
+impl PackageContext<'_> {
     pub fn new() -> Self {
         Self {
             pack_info: PackageInfo::default(),
@@ -37,6 +87,13 @@ impl PackageContext {
             dep_infos: vec![],
             sigs: vec![],
             root_cas: vec![],
+            intermediate_cas: vec![],
+            vcs_info: None,
+            locked_packages: vec![],
+            features: BTreeMap::new(),
+            targets: vec![],
+            unknown_sections: vec![],
+            base_str_table: Cow::Borrowed(&[]),
         }
     }
 
@@ -47,12 +104,7 @@ impl PackageContext {
         license: String,
         authors: Vec<String>,
     ) {
-        self.pack_info = PackageInfo {
-            name,
-            version,
-            license,
-            authors,
-        }
+        self.pack_info = PackageInfo::new(name, version, license, authors)
     }
 
     pub fn add_dep_info(
@@ -67,10 +119,14 @@ impl PackageContext {
             ver_req,
             src,
             src_platform,
-            dump: true,
+            ..Default::default()
         });
     }
 
+    pub fn add_dep(&mut self, dep_info: DepInfo) {
+        self.dep_infos.push(dep_info);
+    }
+
     pub fn dep_num(&self) -> usize {
         self.dep_infos.len()
     }
@@ -78,10 +134,7 @@ impl PackageContext {
     pub fn add_sig(&mut self, pkcs: PKCS, sign_type: SIGTYPE) -> usize {
         let mut siginfo = SigInfo::new();
         siginfo.pkcs = pkcs;
-        match sign_type {
-            SIGTYPE::FILE => siginfo.typ = 0,
-            SIGTYPE::CRATEBIN => siginfo.typ = 1,
-        }
+        siginfo.typ = sign_type.typ();
         self.sigs.push(siginfo);
         self.sigs.len() - 1
     }
@@ -98,6 +151,10 @@ impl PackageContext {
         self.root_cas.push(root_ca);
     }
 
+    pub fn set_intermediate_cas_bin(&mut self, intermediate_ca_bins: Vec<Vec<u8>>) {
+        self.intermediate_cas = intermediate_ca_bins;
+    }
+
     pub fn add_crate_bin(&mut self, bin: Vec<u8>) {
         let mut c = CrateBinary::new();
         c.set_bin(bin);
@@ -105,30 +162,296 @@ impl PackageContext {
     }
 }
 
-impl Default for PackageContext {
+impl Default for PackageContext<'_> {
     fn default() -> Self {
         Self::new()
     }
 }
 
+impl PackageContext<'_> {
+    ///machine-readable metadata of the package, its dependencies and signers
+    pub fn metadata(&self) -> PackageMetadata {
+        let mut signers = vec![];
+        for (signature, siginfo) in self.sigs.iter().enumerate() {
+            //verified signers know their root, unverified ones are read as is
+            let signer_certs = if siginfo.signers.is_empty() {
+                PKCS::signer_certs(siginfo.bin.as_slice())
+            } else {
+                siginfo.signers.clone()
+            };
+            for signer in signer_certs {
+                signers.push(SignerInfo {
+                    signature,
+                    sig_type: sig_type_name(siginfo.typ).to_string(),
+                    signer,
+                });
+            }
+        }
+        PackageMetadata {
+            metadata_version: METADATA_VERSION,
+            package: self.pack_info.clone(),
+            dependencies: self.dep_infos.clone(),
+            vcs: self.vcs_info.clone(),
+            locked_packages: self.locked_packages.clone(),
+            features: self.features.clone(),
+            targets: self.targets.clone(),
+            signers,
+        }
+    }
+}
+
+///serialized as its metadata, binary and signatures are left out
+impl Serialize for PackageContext<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.metadata().serialize(serializer)
+    }
+}
+
+///version of the metadata document schema, bumped on incompatible changes
+pub const METADATA_VERSION: u32 = 1;
+
+///metadata document written next to the extracted crate, see README for the schema
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PackageMetadata {
+    pub metadata_version: u32,
+    pub package: PackageInfo,
+    pub dependencies: Vec<DepInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vcs: Option<VcsInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locked_packages: Vec<LockedPackage>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<TargetInfo>,
+    pub signers: Vec<SignerInfo>,
+}
+
+impl PackageMetadata {
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::Serialize(e.to_string()))
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string(self).map_err(|e| Error::Serialize(e.to_string()))
+    }
+}
+
+///identity of the certificate that made a signature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignerInfo {
+    ///index of the signature section it signed
+    #[serde(default)]
+    pub signature: usize,
+    pub sig_type: String,
+    #[serde(flatten)]
+    pub signer: SignerCert,
+}
+
+///version control state of the packaged source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VcsInfo {
+    ///only `git` for now
+    pub vcs: String,
+    pub commit: String,
+    ///the working tree had uncommitted changes
+    pub dirty: bool,
+    ///directory of the package inside the repository, empty at its root
+    pub path_in_vcs: String,
+}
+
+impl VcsInfo {
+    pub fn write_to_vcs_info_section(
+        &self,
+        vis: &mut VcsInfoSection,
+        str_table: &mut StringTable,
+    ) -> Result<(), Error> {
+        vis.vcs_type = 0;
+        vis.vcs_commit = str_table.insert_str(self.commit.clone())?;
+        vis.vcs_dirty = self.dirty as Uchar;
+        vis.vcs_path = str_table.insert_str(self.path_in_vcs.clone())?;
+        Ok(())
+    }
+
+    pub fn read_from_vcs_info_section(
+        vis: &VcsInfoSection,
+        str_table: &StringTable,
+    ) -> Result<Self, Error> {
+        if vis.vcs_type != 0 {
+            return Err(Error::Format(format!(
+                "vcs_type {} not valid!",
+                vis.vcs_type
+            )));
+        }
+        Ok(Self {
+            vcs: "git".to_string(),
+            commit: str_table.str_by_off(&vis.vcs_commit)?,
+            dirty: vis.vcs_dirty != 0,
+            path_in_vcs: str_table.str_by_off(&vis.vcs_path)?,
+        })
+    }
+}
+
+///a package the crate was built and tested against, as pinned by Cargo.lock
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    ///cargo source id, empty for path dependencies
+    pub source: String,
+    ///sha256 of the registry `.crate`, empty for path and git dependencies
+    pub checksum: String,
+}
+
+impl LockedPackage {
+    pub fn write_to_lockfile_entry(
+        &self,
+        le: &mut LockfileEntry,
+        str_table: &mut StringTable,
+    ) -> Result<(), Error> {
+        le.lock_name = str_table.insert_str(self.name.clone())?;
+        le.lock_version = str_table.insert_str(self.version.clone())?;
+        le.lock_source = str_table.insert_str(self.source.clone())?;
+        le.lock_checksum = str_table.insert_str(self.checksum.clone())?;
+        Ok(())
+    }
+
+    pub fn read_from_lockfile_entry(
+        le: &LockfileEntry,
+        str_table: &StringTable,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            name: str_table.str_by_off(&le.lock_name)?,
+            version: str_table.str_by_off(&le.lock_version)?,
+            source: str_table.str_by_off(&le.lock_source)?,
+            checksum: str_table.str_by_off(&le.lock_checksum)?,
+        })
+    }
+}
+
+pub fn write_to_features_section(
+    features: &BTreeMap<String, Vec<String>>,
+    fs: &mut FeaturesSection,
+    str_table: &mut StringTable,
+) -> Result<(), Error> {
+    let entries = features
+        .iter()
+        .map(|(name, items)| {
+            Ok(FeatureEntry {
+                feat_name: str_table.insert_str(name.clone())?,
+                feat_items: str_offs(items, str_table)?,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    fs.entries = LenArrayType::from_vec(entries);
+    Ok(())
+}
+
+pub fn read_from_features_section(
+    fs: &FeaturesSection,
+    str_table: &StringTable,
+) -> Result<BTreeMap<String, Vec<String>>, Error> {
+    let mut features = BTreeMap::new();
+    for entry in fs.entries.arr.iter() {
+        features.insert(
+            str_table.str_by_off(&entry.feat_name)?,
+            strs_by_offs(&entry.feat_items, str_table)?,
+        );
+    }
+    Ok(features)
+}
+
+///a lib or bin target of the package
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TargetInfo {
+    pub name: String,
+    ///`bin`, or the lib kinds such as `lib`, `cdylib` and `proc-macro`
+    pub kind: Vec<String>,
+    ///entry point, relative to the package root
+    pub src_path: String,
+}
+
+impl TargetInfo {
+    pub fn write_to_target_entry(
+        &self,
+        te: &mut TargetEntry,
+        str_table: &mut StringTable,
+    ) -> Result<(), Error> {
+        te.target_name = str_table.insert_str(self.name.clone())?;
+        te.target_kinds = str_offs(&self.kind, str_table)?;
+        te.target_path = str_table.insert_str(self.src_path.clone())?;
+        Ok(())
+    }
+
+    pub fn read_from_target_entry(
+        te: &TargetEntry,
+        str_table: &StringTable,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            name: str_table.str_by_off(&te.target_name)?,
+            kind: strs_by_offs(&te.target_kinds, str_table)?,
+            src_path: str_table.str_by_off(&te.target_path)?,
+        })
+    }
+}
+
+pub fn write_to_targets_section(
+    targets: &[TargetInfo],
+    ts: &mut TargetsSection,
+    str_table: &mut StringTable,
+) -> Result<(), Error> {
+    let mut entries = vec![];
+    for target in targets.iter() {
+        let mut entry = TargetEntry::default();
+        target.write_to_target_entry(&mut entry, str_table)?;
+        entries.push(entry);
+    }
+    ts.entries = LenArrayType::from_vec(entries);
+    Ok(())
+}
+
+pub fn read_from_targets_section(
+    ts: &TargetsSection,
+    str_table: &StringTable,
+) -> Result<Vec<TargetInfo>, Error> {
+    ts.entries
+        .arr
+        .iter()
+        .map(|entry| TargetInfo::read_from_target_entry(entry, str_table))
+        .collect()
+}
+
 ///package's info
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct PackageInfo {
     pub name: String,
     pub version: String,
     pub license: String,
     pub authors: Vec<String>,
-}
-
-impl Default for PackageInfo {
-    fn default() -> Self {
-        Self {
-            name: "".to_string(),
-            version: "".to_string(),
-            license: "".to_string(),
-            authors: vec![],
-        }
-    }
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub documentation: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub homepage: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub repository: String,
+    ///path of the readme inside the package
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub readme: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub edition: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub rust_version: String,
+    ///name of the native library the package links
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub links: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub license_file: String,
 }
 
 impl PackageInfo {
@@ -138,42 +461,114 @@ impl PackageInfo {
             version,
             license,
             authors,
+            ..Default::default()
         }
     }
 
-    pub fn write_to_package_section(&self, ps: &mut PackageSection, str_table: &mut StringTable) {
-        ps.pkg_name = str_table.insert_str(self.name.clone());
-        ps.pkg_version = str_table.insert_str(self.version.clone());
-        ps.pkg_license = str_table.insert_str(self.license.clone());
-        let mut authors_off = vec![];
-        self.authors.iter().for_each(|author| {
-            authors_off.push(str_table.insert_str(author.clone()));
-        });
-        ps.pkg_authors = LenArrayType::copy_from_vec(&authors_off);
+    pub fn write_to_package_section(
+        &self,
+        ps: &mut PackageSection,
+        str_table: &mut StringTable,
+    ) -> Result<(), Error> {
+        ps.pkg_name = str_table.insert_str(self.name.clone())?;
+        ps.pkg_version = str_table.insert_str(self.version.clone())?;
+        ps.pkg_license = str_table.insert_str(self.license.clone())?;
+        ps.pkg_authors = str_offs(&self.authors, str_table)?;
+        ps.pkg_description = str_table.insert_str(self.description.clone())?;
+        ps.pkg_documentation = str_table.insert_str(self.documentation.clone())?;
+        ps.pkg_homepage = str_table.insert_str(self.homepage.clone())?;
+        ps.pkg_repository = str_table.insert_str(self.repository.clone())?;
+        ps.pkg_readme = str_table.insert_str(self.readme.clone())?;
+        ps.pkg_keywords = str_offs(&self.keywords, str_table)?;
+        ps.pkg_categories = str_offs(&self.categories, str_table)?;
+        ps.pkg_edition = str_table.insert_str(self.edition.clone())?;
+        ps.pkg_rust_version = str_table.insert_str(self.rust_version.clone())?;
+        ps.pkg_links = str_table.insert_str(self.links.clone())?;
+        ps.pkg_license_file = str_table.insert_str(self.license_file.clone())?;
+        Ok(())
     }
 
-    pub fn read_from_package_section(&mut self, ps: &PackageSection, str_table: &StringTable) {
-        self.name = str_table.str_by_off(&ps.pkg_name);
-        self.version = str_table.str_by_off(&ps.pkg_version);
-        self.license = str_table.str_by_off(&ps.pkg_license);
-        let authors_off = ps.pkg_authors.to_vec();
-        authors_off.iter().for_each(|author_off| {
-            self.authors.push(str_table.str_by_off(author_off));
-        });
+    pub fn read_from_package_section(
+        &mut self,
+        ps: &PackageSection,
+        str_table: &StringTable,
+    ) -> Result<(), Error> {
+        self.name = str_table.str_by_off(&ps.pkg_name)?;
+        self.version = str_table.str_by_off(&ps.pkg_version)?;
+        self.license = str_table.str_by_off(&ps.pkg_license)?;
+        self.authors = strs_by_offs(&ps.pkg_authors, str_table)?;
+        self.description = str_table.str_by_off(&ps.pkg_description)?;
+        self.documentation = str_table.str_by_off(&ps.pkg_documentation)?;
+        self.homepage = str_table.str_by_off(&ps.pkg_homepage)?;
+        self.repository = str_table.str_by_off(&ps.pkg_repository)?;
+        self.readme = str_table.str_by_off(&ps.pkg_readme)?;
+        self.keywords = strs_by_offs(&ps.pkg_keywords, str_table)?;
+        self.categories = strs_by_offs(&ps.pkg_categories, str_table)?;
+        self.edition = str_table.str_by_off(&ps.pkg_edition)?;
+        self.rust_version = str_table.str_by_off(&ps.pkg_rust_version)?;
+        self.links = str_table.str_by_off(&ps.pkg_links)?;
+        self.license_file = str_table.str_by_off(&ps.pkg_license_file)?;
+        Ok(())
     }
 }
 
+fn str_offs(strs: &[String], str_table: &mut StringTable) -> Result<LenArrayType<u32>, Error> {
+    let offs = strs
+        .iter()
+        .map(|st| str_table.insert_str(st.clone()))
+        .collect::<Result<Vec<u32>, Error>>()?;
+    Ok(LenArrayType::copy_from_vec(&offs))
+}
+
+fn strs_by_offs(offs: &LenArrayType<u32>, str_table: &StringTable) -> Result<Vec<String>, Error> {
+    offs.arr
+        .iter()
+        .map(|off| str_table.str_by_off(off))
+        .collect()
+}
+
+///part of a manifest that isn't recorded in the package
+#[derive(Debug, Clone, PartialEq)]
+pub enum Skipped {
+    ///path-only dev-dependency, cargo strips it when packaging
+    Dep(String),
+    ///(dependency, key) the dependency table has no field for
+    DepKey(String, String),
+}
+
 ///dependencies' info
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DepInfo {
     pub name: String,
     pub ver_req: String,
     pub src: SrcTypePath,
     pub src_platform: String,
+    #[serde(default)]
+    pub kind: DepKind,
+    #[serde(default)]
+    pub features: Vec<String>,
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default = "default_features_default")]
+    pub default_features: bool,
+    ///real package name when the dependency is renamed, empty otherwise
+    #[serde(default)]
+    pub package: String,
+    #[serde(default)]
+    pub git_ref: GitRef,
     ///only dump dependency that can be written to crate dependency table section
+    #[serde(skip, default = "dump_default")]
     pub dump: bool,
 }
 
+fn dump_default() -> bool {
+    true
+}
+
+fn default_features_default() -> bool {
+    true
+}
+
 impl Default for DepInfo {
     fn default() -> Self {
         Self {
@@ -181,6 +576,12 @@ impl Default for DepInfo {
             ver_req: "default".to_string(),
             src: SrcTypePath::CratesIo,
             src_platform: "default".to_string(),
+            kind: DepKind::Normal,
+            features: vec![],
+            optional: false,
+            default_features: true,
+            package: "".to_string(),
+            git_ref: GitRef::DefaultBranch,
             dump: true,
         }
     }
@@ -192,6 +593,7 @@ impl DepInfo {
         ver_req: String,
         src: SrcTypePath,
         src_platform: String,
+        kind: DepKind,
         dump: bool,
     ) -> Self {
         Self {
@@ -199,68 +601,176 @@ impl DepInfo {
             ver_req,
             src,
             src_platform,
+            kind,
             dump,
+            ..Default::default()
         }
     }
 
-    pub fn write_to_dep_table_entry(&self, dte: &mut DepTableEntry, str_table: &mut StringTable) {
-        dte.dep_name = str_table.insert_str(self.name.clone());
-        dte.dep_verreq = str_table.insert_str(self.ver_req.clone());
+    pub fn write_to_dep_table_entry(
+        &self,
+        dte: &mut DepTableEntry,
+        str_table: &mut StringTable,
+    ) -> Result<(), Error> {
+        dte.dep_name = str_table.insert_str(self.name.clone())?;
+        dte.dep_verreq = str_table.insert_str(self.ver_req.clone())?;
         match &self.src {
             SrcTypePath::CratesIo => {
                 dte.dep_srctype = 0;
-                dte.dep_srcpath = str_table.insert_str("".to_string());
+                dte.dep_srcpath = str_table.insert_str("".to_string())?;
             }
             SrcTypePath::Git(str) => {
                 dte.dep_srctype = 1;
-                dte.dep_srcpath = str_table.insert_str(str.clone());
+                dte.dep_srcpath = str_table.insert_str(str.clone())?;
             }
             SrcTypePath::Url(str) => {
                 dte.dep_srctype = 2;
-                dte.dep_srcpath = str_table.insert_str(str.clone());
+                dte.dep_srcpath = str_table.insert_str(str.clone())?;
             }
             SrcTypePath::Registry(str) => {
                 dte.dep_srctype = 3;
-                dte.dep_srcpath = str_table.insert_str(str.clone());
+                dte.dep_srcpath = str_table.insert_str(str.clone())?;
             }
             SrcTypePath::P2p(str) => {
                 dte.dep_srctype = 4;
-                dte.dep_srcpath = str_table.insert_str(str.clone());
+                dte.dep_srcpath = str_table.insert_str(str.clone())?;
             }
         }
-        dte.dep_platform = str_table.insert_str(self.src_platform.to_string());
+        dte.dep_platform = str_table.insert_str(self.src_platform.to_string())?;
+        dte.dep_kind = self.kind.typ();
+        dte.dep_flags = 0;
+        if self.optional {
+            dte.dep_flags |= DEP_FLAG_OPTIONAL;
+        }
+        if !self.default_features {
+            dte.dep_flags |= DEP_FLAG_NO_DEFAULT_FEATURES;
+        }
+        dte.dep_package = str_table.insert_str(self.package.clone())?;
+        let (gitreftype, gitref) = match &self.git_ref {
+            GitRef::DefaultBranch => (0, ""),
+            GitRef::Branch(str) => (1, str.as_str()),
+            GitRef::Tag(str) => (2, str.as_str()),
+            GitRef::Rev(str) => (3, str.as_str()),
+        };
+        dte.dep_gitreftype = gitreftype;
+        dte.dep_gitref = str_table.insert_str(gitref.to_string())?;
+        dte.dep_features = str_offs(&self.features, str_table)?;
+        Ok(())
     }
 
-    pub fn read_from_dep_table_entry(&mut self, dte: &DepTableEntry, str_table: &StringTable) {
+    pub fn read_from_dep_table_entry(
+        &mut self,
+        dte: &DepTableEntry,
+        str_table: &StringTable,
+    ) -> Result<(), Error> {
         self.dump = true;
-        self.name = str_table.str_by_off(&dte.dep_name);
-        self.ver_req = str_table.str_by_off(&dte.dep_verreq);
+        self.name = str_table.str_by_off(&dte.dep_name)?;
+        self.ver_req = str_table.str_by_off(&dte.dep_verreq)?;
         match dte.dep_srctype {
             0 => {
                 self.src = SrcTypePath::CratesIo;
             }
             1 => {
-                self.src = SrcTypePath::Git(str_table.str_by_off(&dte.dep_srcpath));
+                self.src = SrcTypePath::Git(str_table.str_by_off(&dte.dep_srcpath)?);
             }
             2 => {
-                self.src = SrcTypePath::Url(str_table.str_by_off(&dte.dep_srcpath));
+                self.src = SrcTypePath::Url(str_table.str_by_off(&dte.dep_srcpath)?);
             }
             3 => {
-                self.src = SrcTypePath::Registry(str_table.str_by_off(&dte.dep_srcpath));
+                self.src = SrcTypePath::Registry(str_table.str_by_off(&dte.dep_srcpath)?);
             }
             4 => {
-                self.src = SrcTypePath::P2p(str_table.str_by_off(&dte.dep_srcpath));
+                self.src = SrcTypePath::P2p(str_table.str_by_off(&dte.dep_srcpath)?);
             }
-            _ => {
-                panic!("dep_srctype not valid!")
+            typ => {
+                return Err(Error::Format(format!("dep_srctype {} not valid!", typ)));
             }
         }
-        self.src_platform = str_table.str_by_off(&dte.dep_platform);
+        self.src_platform = str_table.str_by_off(&dte.dep_platform)?;
+        self.kind = DepKind::from_typ(dte.dep_kind)
+            .ok_or_else(|| Error::Format(format!("dep_kind {} not valid!", dte.dep_kind)))?;
+        self.optional = dte.dep_flags & DEP_FLAG_OPTIONAL != 0;
+        self.default_features = dte.dep_flags & DEP_FLAG_NO_DEFAULT_FEATURES == 0;
+        self.package = str_table.str_by_off(&dte.dep_package)?;
+        let gitref = str_table.str_by_off(&dte.dep_gitref)?;
+        self.git_ref = match dte.dep_gitreftype {
+            0 => GitRef::DefaultBranch,
+            1 => GitRef::Branch(gitref),
+            2 => GitRef::Tag(gitref),
+            3 => GitRef::Rev(gitref),
+            typ => {
+                return Err(Error::Format(format!("dep_gitreftype {} not valid!", typ)));
+            }
+        };
+        self.features = strs_by_offs(&dte.dep_features, str_table)?;
+        Ok(())
     }
 }
 
+///which manifest table a dependency comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DepKind {
+    ///`[dependencies]`
+    #[default]
+    Normal,
+    ///`[dev-dependencies]`
+    Dev,
+    ///`[build-dependencies]`
+    Build,
+}
+
+impl DepKind {
+    pub fn from_typ(typ: Type) -> Option<Self> {
+        match typ {
+            0 => Some(DepKind::Normal),
+            1 => Some(DepKind::Dev),
+            2 => Some(DepKind::Build),
+            _ => None,
+        }
+    }
+
+    pub fn typ(&self) -> Type {
+        match self {
+            DepKind::Normal => 0,
+            DepKind::Dev => 1,
+            DepKind::Build => 2,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DepKind::Normal => "normal",
+            DepKind::Dev => "dev",
+            DepKind::Build => "build",
+        }
+    }
+
+    ///manifest table of this kind, e.g. `dev-dependencies`
+    pub fn table(&self) -> &'static str {
+        match self {
+            DepKind::Normal => "dependencies",
+            DepKind::Dev => "dev-dependencies",
+            DepKind::Build => "build-dependencies",
+        }
+    }
+}
+
+///branch, tag or rev of a git dependency
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "name", rename_all = "kebab-case")]
+pub enum GitRef {
+    ///no ref given, or not a git dependency
+    #[default]
+    DefaultBranch,
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
 ///dependencies' src type and path
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "path", rename_all = "kebab-case")]
 pub enum SrcTypePath {
     CratesIo,
     Git(String),
@@ -273,7 +783,7 @@ pub enum SrcTypePath {
 pub struct StringTable {
     str2off: HashMap<String, u32>,
     off2str: HashMap<u32, String>,
-    total_bytes: u32,
+    total_bytes: u64,
 }
 
 impl Default for StringTable {
@@ -284,26 +794,32 @@ impl Default for StringTable {
 
 impl StringTable {
     pub fn new() -> Self {
-        let mut new_str_table = Self {
-            str2off: Default::default(),
-            off2str: Default::default(),
-            total_bytes: 0,
-        };
-        new_str_table.insert_str("".to_string());
-        new_str_table
-    }
-
-    pub fn insert_str(&mut self, st: String) -> u32 {
-        return if self.str2off.contains_key(&st) {
-            *self.str2off.get(&st).unwrap()
-        } else {
-            let st_len = st.as_bytes().len() as u32;
-            let ret_val = self.total_bytes;
-            self.str2off.insert(st.clone(), self.total_bytes);
-            self.off2str.insert(self.total_bytes, st.clone());
-            self.total_bytes += 4 + st_len;
-            ret_val
-        };
+        //the empty string always sits at offset 0
+        Self {
+            str2off: HashMap::from([("".to_string(), 0)]),
+            off2str: HashMap::from([(0, "".to_string())]),
+            total_bytes: 4,
+        }
+    }
+
+    ///offset of `st`, appended if it's not in the table yet.
+    ///offsets are 4 bytes, so the whole table is capped at 4 GiB
+    pub fn insert_str(&mut self, st: String) -> Result<u32, Error> {
+        if let Some(off) = self.str2off.get(&st) {
+            return Ok(*off);
+        }
+        let err = || Error::Format("string table larger than 4 GiB".to_string());
+        let off = u32::try_from(self.total_bytes).map_err(|_| err())?;
+        let total_bytes = self
+            .total_bytes
+            .checked_add(4)
+            .and_then(|total| total.checked_add(st.len() as u64))
+            .filter(|total| *total <= u32::MAX as u64)
+            .ok_or_else(err)?;
+        self.str2off.insert(st.clone(), off);
+        self.off2str.insert(off, st);
+        self.total_bytes = total_bytes;
+        Ok(off)
     }
 
     pub fn contains_str(&self, st: &String) -> bool {
@@ -314,8 +830,22 @@ impl StringTable {
         *self.str2off.get(st).unwrap()
     }
 
-    pub fn str_by_off(&self, off: &u32) -> String {
-        self.off2str.get(off).unwrap().clone()
+    pub fn str_by_off(&self, off: &u32) -> Result<String, Error> {
+        self.off2str
+            .get(off)
+            .cloned()
+            .ok_or_else(|| Error::Format(format!("string table offset {} not found", off)))
+    }
+
+    ///all (offset, string) pairs ordered by offset
+    pub fn entries(&self) -> Vec<(u32, String)> {
+        let mut entries: Vec<_> = self
+            .off2str
+            .iter()
+            .map(|(off, st)| (*off, st.clone()))
+            .collect();
+        entries.sort();
+        entries
     }
 
     ///dump string table to bytes
@@ -332,49 +862,65 @@ impl StringTable {
         bytes
     }
 
-    ///parse string table from bytes
-    pub fn read_bytes(&mut self, bytes: &[u8]) {
+    ///parse string table from bytes, every length and string is checked
+    pub fn read_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let err = |msg: &str| Error::Format(format!("string table not right! - {}", msg));
+        if u32::try_from(bytes.len()).is_err() {
+            return Err(err("too large"));
+        }
+        self.str2off.clear();
+        self.off2str.clear();
         let mut i = 0;
         while i < bytes.len() {
-            let mut len_bytes: [u8; 4] = [0; 4];
-            len_bytes.copy_from_slice(bytes[i..i + 4].as_ref());
+            let len_bytes: [u8; 4] = bytes
+                .get(i..i + 4)
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(|| err("length truncated"))?;
             let len = u32::from_le_bytes(len_bytes) as usize;
-            let st = String::from_utf8(bytes[i + 4..i + 4 + len].to_vec()).unwrap();
-            self.str2off.insert(st.clone(), i as u32);
+            let st_bytes = (i + 4)
+                .checked_add(len)
+                .and_then(|end| bytes.get(i + 4..end))
+                .ok_or_else(|| err("string truncated"))?;
+            let st = String::from_utf8(st_bytes.to_vec()).map_err(|_| err("invalid utf-8"))?;
+            self.str2off.entry(st.clone()).or_insert(i as u32);
             self.off2str.insert(i as u32, st);
             i += 4 + len;
-            self.total_bytes = i as u32;
         }
+        self.total_bytes = i as u64;
+        Ok(())
     }
 }
 
+///the `.crate` file, decoded from a package it is borrowed from the package's bytes
 #[derive(Debug, PartialEq)]
-pub struct CrateBinary {
-    //FIXME this maybe change to for fast read
-    pub bytes: Vec<u8>,
+pub struct CrateBinary<'a> {
+    pub bytes: Cow<'a, [u8]>,
 }
 
-impl Default for CrateBinary {
+impl Default for CrateBinary<'_> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl CrateBinary {
+impl<'a> CrateBinary<'a> {
     pub fn new() -> Self {
-        Self { bytes: vec![] }
+        Self {
+            bytes: Cow::Borrowed(&[]),
+        }
     }
 
     pub fn set_bin(&mut self, bytes: Vec<u8>) {
-        self.bytes = bytes;
+        self.bytes = Cow::Owned(bytes);
     }
 
-    pub fn write_to_crate_binary_section(&self, cbs: &mut CrateBinarySection) {
-        cbs.bin.arr = self.bytes.to_vec();
+    ///the section borrows the bytes if this does
+    pub fn write_to_crate_binary_section(&self, cbs: &mut CrateBinarySection<'a>) {
+        cbs.bin = self.bytes.clone();
     }
 
-    pub fn read_from_crate_biary_section(&mut self, cbs: &CrateBinarySection) {
-        self.bytes = cbs.bin.arr.to_vec();
+    pub fn read_from_crate_biary_section(&mut self, cbs: &CrateBinarySection<'a>) {
+        self.bytes = cbs.bin.clone();
     }
 }
 
@@ -384,6 +930,8 @@ pub struct SigInfo {
     pub size: usize,
     pub bin: Vec<u8>,
     pub pkcs: PKCS,
+    ///filled once the signature is verified
+    pub signers: Vec<SignerCert>,
 }
 
 impl Default for SigInfo {
@@ -399,6 +947,7 @@ impl SigInfo {
             size: 0,
             bin: vec![],
             pkcs: PKCS::new(),
+            signers: vec![],
         }
     }
 
@@ -416,3 +965,85 @@ impl SigInfo {
         sig.sigstruct_sig = RawArrayType::from_vec(self.bin.clone());
     }
 }
+
+#[test]
+fn test_metadata() {
+    let mut pkcs = PKCS::new();
+    pkcs.load_from_file_writer(
+        "test/cert.pem".to_string(),
+        "test/key.pem".to_string(),
+        ["test/root-ca.pem".to_string()].to_vec(),
+    )
+    .unwrap();
+    let mut package_context = PackageContext::new();
+    package_context.set_package_info(
+        "rust-crate".to_string(),
+        "1.0.0".to_string(),
+        "MIT".to_string(),
+        vec!["rust".to_string()],
+    );
+    package_context.add_dep_info(
+        "crate-spec".to_string(),
+        ">=0.8.0".to_string(),
+        SrcTypePath::Git("http://git.com".to_string()),
+        "windows".to_string(),
+    );
+    package_context.add_crate_bin([15; 100].to_vec());
+    package_context.add_sig(pkcs, SIGTYPE::CRATEBIN);
+    package_context.encode_to_crate_package().unwrap();
+
+    let metadata = package_context.metadata();
+    assert_eq!(metadata.metadata_version, METADATA_VERSION);
+    assert_eq!(metadata.signers.len(), 1);
+    assert_eq!(metadata.signers[0].signature, 0);
+    assert_eq!(metadata.signers[0].sig_type, "crate-bin");
+    assert!(metadata.signers[0]
+        .signer
+        .subject
+        .ends_with("CN=foobar.com"));
+
+    let json: serde_json::Value =
+        serde_json::from_str(metadata.to_json().unwrap().as_str()).unwrap();
+    assert_eq!(json["dependencies"][0]["src"]["type"], "git");
+    assert_eq!(json["dependencies"][0]["src"]["path"], "http://git.com");
+    assert_eq!(
+        serde_json::from_str::<PackageMetadata>(metadata.to_json().unwrap().as_str()).unwrap(),
+        metadata
+    );
+    assert_eq!(
+        toml::from_str::<PackageMetadata>(metadata.to_toml().unwrap().as_str()).unwrap(),
+        metadata
+    );
+}
+
+#[test]
+fn test_string_table_overflow() {
+    let mut str_table = StringTable::new();
+    str_table.total_bytes = u32::MAX as u64 - 5;
+    assert!(str_table.insert_str("a".to_string()).is_ok());
+    assert!(str_table.insert_str("".to_string()).is_ok());
+    assert!(str_table.insert_str("b".to_string()).is_err());
+    str_table.total_bytes = u32::MAX as u64 + 1;
+    assert!(str_table.insert_str("c".to_string()).is_err());
+}
+
+#[test]
+fn test_string_table_read_bytes() {
+    let mut str_table = StringTable::new();
+    str_table.insert_str("rust".to_string()).unwrap();
+    let bytes = str_table.to_bytes();
+
+    let mut str_table_new = StringTable::new();
+    str_table_new.read_bytes(&bytes).unwrap();
+    assert_eq!(str_table_new.str_by_off(&4).unwrap(), "rust");
+    assert!(str_table_new.str_by_off(&5).is_err());
+
+    assert!(StringTable::new().read_bytes(&bytes[..6]).is_err());
+    assert!(StringTable::new().read_bytes(&[4, 0, 0]).is_err());
+    assert!(StringTable::new()
+        .read_bytes(&[0xff, 0xff, 0xff, 0xff, b'a'])
+        .is_err());
+    assert!(StringTable::new()
+        .read_bytes(&[2, 0, 0, 0, 0xc3, 0x28])
+        .is_err());
+}
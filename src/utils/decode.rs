@@ -1,81 +1,192 @@
-use crate::utils::context::{DepInfo, PackageContext, SigInfo, StringTable};
+use crate::utils::context::{
+    read_from_features_section, read_from_targets_section, DepInfo, LockedPackage, PackageContext,
+    SigInfo, StringTable, VcsInfo, SIGTYPE,
+};
+use crate::utils::error::Error;
+use crate::utils::package::gen_bincode::encode2vec_by_bincode;
 use crate::utils::package::{
-    CrateBinarySection, CratePackage, DataSection, DepTableSection, PackageSection, SectionIndex,
-    SigStructureSection, FINGERPRINT_LEN,
+    format_version, section_kind, CrateBinarySection, CratePackage, DataSection, DepTableSection,
+    FeaturesSection, LockfileSection, Off, PackageSection, SectionIndex, SigStructureSection, Size,
+    TargetsSection, VcsInfoSection, FINGERPRINT_LEN, MAGIC_NUMBER,
 };
 
 use crate::utils::pkcs::PKCS;
+use std::collections::BTreeMap;
 
 impl SectionIndex {
-    pub fn section_id_by_type(&self, typ: usize) -> usize {
+    pub fn section_id_by_type(&self, typ: usize) -> Result<usize, Error> {
         for (i, entry) in self.entries.arr.iter().enumerate() {
-            if entry.sh_type as usize == typ {
-                return i;
+            if section_kind(entry.sh_type) as usize == typ {
+                return Ok(i);
             }
         }
-        panic!("section typ not found")
+        Err(Error::Format("section typ not found".to_string()))
     }
 }
 
-impl CratePackage {
-    pub fn data_section_by_id(&self, id: usize) -> &DataSection {
-        &self.data_sections.col.arr[id]
+impl<'a> CratePackage<'a> {
+    pub fn data_section_by_id(&self, id: usize) -> Result<&DataSection<'a>, Error> {
+        self.data_sections
+            .col
+            .arr
+            .get(id)
+            .ok_or_else(|| Error::Format("section id not found".to_string()))
     }
 
-    pub fn data_section_by_type(&self, typ: usize) -> &DataSection {
-        self.data_section_by_id(self.section_index.section_id_by_type(typ))
+    pub fn data_section_by_type(&self, typ: usize) -> Result<&DataSection<'a>, Error> {
+        self.data_section_by_id(self.section_index.section_id_by_type(typ)?)
     }
 
-    pub fn package_section(&self) -> &PackageSection {
+    pub fn package_section(&self) -> Result<&PackageSection, Error> {
         //FIXME: 0 should be constant
-        match self.data_section_by_type(0) {
-            DataSection::PackageSection(pak) => pak,
-            _ => {
-                panic!("package section not found!")
-            }
+        match self.data_section_by_type(0)? {
+            DataSection::PackageSection(pak) => Ok(pak),
+            _ => Err(Error::Format("package section not found!".to_string())),
         }
     }
 
-    pub fn dep_table_section(&self) -> &DepTableSection {
-        match self.data_section_by_type(1) {
-            DataSection::DepTableSection(dep) => dep,
-            _ => {
-                panic!("dep table section not found!")
-            }
+    pub fn dep_table_section(&self) -> Result<&DepTableSection, Error> {
+        match self.data_section_by_type(1)? {
+            DataSection::DepTableSection(dep) => Ok(dep),
+            _ => Err(Error::Format("dep table section not found!".to_string())),
         }
     }
 
-    pub fn crate_binary_section(&self) -> &CrateBinarySection {
-        match self.data_section_by_type(3) {
-            DataSection::CrateBinarySection(cra) => cra,
-            _ => {
-                panic!("crate binary section not found!")
-            }
+    ///the optional vcs info section
+    pub fn vcs_info_section(&self) -> Result<Option<&VcsInfoSection>, Error> {
+        if self.section_index.section_id_by_type(5).is_err() {
+            return Ok(None);
+        }
+        match self.data_section_by_type(5)? {
+            DataSection::VcsInfoSection(vcs) => Ok(Some(vcs)),
+            _ => Err(Error::Format("vcs info section not found!".to_string())),
         }
     }
 
-    pub fn sig_structure_section(&self, no: usize) -> &SigStructureSection {
-        let base = self.section_index.section_id_by_type(4);
-        match self.data_section_by_id(no + base) {
-            DataSection::SigStructureSection(sig) => sig,
-            _ => {
-                panic!("sig structure section not found!")
-            }
+    ///the optional lockfile section
+    pub fn lockfile_section(&self) -> Result<Option<&LockfileSection>, Error> {
+        if self.section_index.section_id_by_type(6).is_err() {
+            return Ok(None);
+        }
+        match self.data_section_by_type(6)? {
+            DataSection::LockfileSection(lock) => Ok(Some(lock)),
+            _ => Err(Error::Format("lockfile section not found!".to_string())),
+        }
+    }
+
+    ///the optional features section
+    pub fn features_section(&self) -> Result<Option<&FeaturesSection>, Error> {
+        if self.section_index.section_id_by_type(7).is_err() {
+            return Ok(None);
+        }
+        match self.data_section_by_type(7)? {
+            DataSection::FeaturesSection(features) => Ok(Some(features)),
+            _ => Err(Error::Format("features section not found!".to_string())),
+        }
+    }
+
+    ///the optional targets section
+    pub fn targets_section(&self) -> Result<Option<&TargetsSection>, Error> {
+        if self.section_index.section_id_by_type(8).is_err() {
+            return Ok(None);
+        }
+        match self.data_section_by_type(8)? {
+            DataSection::TargetsSection(targets) => Ok(Some(targets)),
+            _ => Err(Error::Format("targets section not found!".to_string())),
+        }
+    }
+
+    pub fn crate_binary_section(&self) -> Result<&CrateBinarySection<'a>, Error> {
+        match self.data_section_by_type(3)? {
+            DataSection::CrateBinarySection(cra) => Ok(cra),
+            _ => Err(Error::Format("crate binary section not found!".to_string())),
+        }
+    }
+
+    ///the package as it would be encoded without any signature,
+    ///signature sections are always the last ones
+    pub fn binary_without_sig(&self, bin: &[u8]) -> Result<Vec<u8>, Error> {
+        let err = || Error::Format("file format not right! - sig".to_string());
+        let header = &self.crate_header;
+        let none_sig_si_size = self.section_index.none_sig_size(self.layout());
+        let sig_si_size = (header.si_size as usize)
+            .checked_sub(none_sig_si_size)
+            .ok_or_else(err)?;
+        let header_end = MAGIC_NUMBER.len() + header.size();
+        let si_offset = header.si_offset as usize;
+        let ds_offset = header.ds_offset as usize;
+
+        let mut unsigned_header = header.clone();
+        unsigned_header.si_num = self.section_index.none_sig_num() as Size;
+        unsigned_header.si_size = none_sig_si_size as Size;
+        unsigned_header.ds_offset = (ds_offset.checked_sub(sig_si_size).ok_or_else(err)?) as Off;
+
+        let mut buf = MAGIC_NUMBER.to_vec();
+        buf.extend(encode2vec_by_bincode(&unsigned_header));
+        buf.extend(
+            bin.get(header_end..si_offset + none_sig_si_size)
+                .ok_or_else(err)?,
+        );
+        buf.extend(
+            bin.get(
+                si_offset + header.si_size as usize
+                    ..ds_offset + self.section_index.datasection_size_without_sig(),
+            )
+            .ok_or_else(err)?,
+        );
+        Ok(buf)
+    }
+
+    pub fn sig_structure_section(&self, no: usize) -> Result<&SigStructureSection, Error> {
+        let base = self.section_index.section_id_by_type(4)?;
+        match self.data_section_by_id(no + base)? {
+            DataSection::SigStructureSection(sig) => Ok(sig),
+            _ => Err(Error::Format(
+                "sig structure section not found!".to_string(),
+            )),
         }
     }
 }
 
-impl PackageContext {
-    pub fn binary_before_sig(&self, crate_package: &CratePackage, bin: &[u8]) -> Vec<u8> {
+impl<'a> PackageContext<'a> {
+    ///the bytes covered by a file signature
+    pub fn binary_before_sig(
+        &self,
+        crate_package: &CratePackage,
+        bin: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        match format_version(crate_package.crate_header.c_version) {
+            0 => self.binary_before_sig_v0(crate_package, bin),
+            _ => crate_package.binary_without_sig(bin),
+        }
+    }
+
+    ///c_version 0: the header and the zero-filled signature index entries are covered,
+    ///so adding or removing a signature breaks every file signature
+    fn binary_before_sig_v0(
+        &self,
+        crate_package: &CratePackage,
+        bin: &[u8],
+    ) -> Result<Vec<u8>, Error> {
         //FIXME
         let ds_size = crate_package.section_index.datasection_size_without_sig();
-        let total_size = crate_package.crate_header.ds_offset as usize + ds_size;
-        if crate_package.section_index.sig_num() != self.sigs.len() && !self.sigs.is_empty() {
-            assert_eq!(crate_package.section_index.sig_num(), 0);
+        let total_size = (crate_package.crate_header.ds_offset as usize)
+            .checked_add(ds_size)
+            .ok_or_else(|| Error::Format("file format not right! - ds".to_string()))?;
+        if crate_package.section_index.sig_num() != self.sigs.len()
+            && !self.sigs.is_empty()
+            && crate_package.section_index.sig_num() != 0
+        {
+            return Err(Error::Format("sig num not right!".to_string()));
+        }
+        if total_size > bin.len() {
+            return Err(Error::Format("file format not right! - ds".to_string()));
         }
         let mut buf = bin[..total_size].to_vec();
         let zero_begin = crate_package.crate_header.si_offset as usize
-            + crate_package.section_index.none_sig_size();
+            + crate_package
+                .section_index
+                .none_sig_size(crate_package.layout());
         let zero_end = crate_package.crate_header.si_offset as usize
             + crate_package.crate_header.si_size as usize;
         //FIXME this is not efficient
@@ -83,102 +194,219 @@ impl PackageContext {
             *i = 0;
         }
 
-        buf
+        Ok(buf)
     }
 
-    pub fn binary_before_digest(&self, bin: &[u8]) -> Vec<u8> {
-        bin[..bin.len() - FINGERPRINT_LEN].to_vec()
+    pub fn binary_before_digest(&self, bin: &[u8]) -> Result<Vec<u8>, Error> {
+        if bin.len() < FINGERPRINT_LEN {
+            return Err(Error::Format(
+                "file format not right! - fingerprint".to_string(),
+            ));
+        }
+        Ok(bin[..bin.len() - FINGERPRINT_LEN].to_vec())
     }
 
-    fn pack_info(&mut self, crate_package: &CratePackage, str_table: &StringTable) {
+    fn pack_info(
+        &mut self,
+        crate_package: &CratePackage,
+        str_table: &StringTable,
+    ) -> Result<(), Error> {
         self.pack_info
-            .read_from_package_section(crate_package.package_section(), str_table);
+            .read_from_package_section(crate_package.package_section()?, str_table)
     }
 
-    fn deps(&mut self, crate_package: &CratePackage, str_table: &StringTable) {
-        for entry in crate_package.dep_table_section().entries.arr.iter() {
+    fn deps(&mut self, crate_package: &CratePackage, str_table: &StringTable) -> Result<(), Error> {
+        for entry in crate_package.dep_table_section()?.entries.arr.iter() {
             let mut dep_info = DepInfo::default();
-            dep_info.read_from_dep_table_entry(entry, str_table);
+            dep_info.read_from_dep_table_entry(entry, str_table)?;
             self.dep_infos.push(dep_info);
         }
+        Ok(())
     }
 
-    fn binary(&mut self, crate_package: &CratePackage) {
-        self.crate_binary.bytes = crate_package.crate_binary_section().bin.arr.clone();
+    fn vcs_info(
+        &mut self,
+        crate_package: &CratePackage,
+        str_table: &StringTable,
+    ) -> Result<(), Error> {
+        self.vcs_info = match crate_package.vcs_info_section()? {
+            Some(vcs) => Some(VcsInfo::read_from_vcs_info_section(vcs, str_table)?),
+            None => None,
+        };
+        Ok(())
     }
 
-    fn sigs(&mut self, crate_package: &CratePackage) {
+    fn locked_packages(
+        &mut self,
+        crate_package: &CratePackage,
+        str_table: &StringTable,
+    ) -> Result<(), Error> {
+        self.locked_packages = vec![];
+        if let Some(lock) = crate_package.lockfile_section()? {
+            for entry in lock.entries.arr.iter() {
+                self.locked_packages
+                    .push(LockedPackage::read_from_lockfile_entry(entry, str_table)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn features(
+        &mut self,
+        crate_package: &CratePackage,
+        str_table: &StringTable,
+    ) -> Result<(), Error> {
+        self.features = match crate_package.features_section()? {
+            Some(features) => read_from_features_section(features, str_table)?,
+            None => BTreeMap::new(),
+        };
+        Ok(())
+    }
+
+    fn targets(
+        &mut self,
+        crate_package: &CratePackage,
+        str_table: &StringTable,
+    ) -> Result<(), Error> {
+        self.targets = match crate_package.targets_section()? {
+            Some(targets) => read_from_targets_section(targets, str_table)?,
+            None => vec![],
+        };
+        Ok(())
+    }
+
+    ///keep sections of unknown non-critical types, and the string table they may point
+    ///into, so encoding the package again carries them over. the string table is kept
+    ///even without them, then the layout file signatures cover stays the same
+    fn unknown_sections(&mut self, crate_package: &CratePackage<'a>) {
+        self.unknown_sections = crate_package
+            .data_sections
+            .col
+            .arr
+            .iter()
+            .filter_map(|section| match section {
+                DataSection::Unknown(unknown) => Some(unknown.clone()),
+                _ => None,
+            })
+            .collect();
+        self.base_str_table = crate_package.string_table.clone();
+    }
+
+    fn binary(&mut self, crate_package: &CratePackage<'a>) -> Result<(), Error> {
+        self.crate_binary
+            .read_from_crate_biary_section(crate_package.crate_binary_section()?);
+        Ok(())
+    }
+
+    fn sigs(&mut self, crate_package: &CratePackage) -> Result<(), Error> {
         let sig_num = crate_package.section_index.sig_num();
         for no in 0..sig_num {
-            let sig = crate_package.sig_structure_section(no);
+            let sig = crate_package.sig_structure_section(no)?;
             let mut sig_info = SigInfo::new();
             sig_info.bin = sig.sigstruct_sig.arr.clone();
             sig_info.size = sig.sigstruct_size as usize;
             sig_info.typ = sig.sigstruct_type as u32;
             self.sigs.push(sig_info);
         }
+        Ok(())
     }
 
-    fn check_fingerprint(&self, bin_all: &[u8]) -> bool {
-        PKCS::new().gen_digest_256(&bin_all[..bin_all.len() - FINGERPRINT_LEN])
-            == bin_all[bin_all.len() - FINGERPRINT_LEN..]
+    fn check_fingerprint(&self, bin_all: &[u8]) -> Result<bool, Error> {
+        if bin_all.len() < FINGERPRINT_LEN {
+            return Ok(false);
+        }
+        Ok(
+            PKCS::new().gen_digest_256(&bin_all[..bin_all.len() - FINGERPRINT_LEN])?
+                == bin_all[bin_all.len() - FINGERPRINT_LEN..],
+        )
     }
 
-    fn check_sigs(&self, crate_package: &CratePackage, bin_all: &[u8]) -> bool {
-        let bin_all = self.binary_before_sig(crate_package, bin_all);
-        let bin_crate = crate_package.crate_binary_section().bin.arr.as_slice();
-        for siginfo in self.sigs.iter() {
-            let actual_digest;
+    ///verify `self.sigs` against the root CAs and record their signers,
+    ///`bin_all` is the whole package file
+    pub fn check_sigs(
+        &mut self,
+        crate_package: &CratePackage,
+        bin_all: &[u8],
+    ) -> Result<(), Error> {
+        let bin_all = if self.sigs.iter().any(|sig| sig.typ == SIGTYPE::FILE.typ()) {
+            self.binary_before_sig(crate_package, bin_all)?
+        } else {
+            vec![]
+        };
+        let bin_crate = &*crate_package.crate_binary_section()?.bin;
+        for siginfo in self.sigs.iter_mut() {
             //FIXME this should be encapsulated as it's used in encode as well
-            match siginfo.typ {
-                0 => {
-                    actual_digest = siginfo.pkcs.gen_digest_256(bin_all.as_slice());
-                }
-                1 => {
-                    actual_digest = siginfo.pkcs.gen_digest_256(bin_crate);
-                }
-                _ => {
-                    panic!("sig type is not right!")
-                }
-            }
-            let expect_digest = PKCS::decode_pkcs_bin(siginfo.bin.as_slice(), &self.root_cas);
-            if actual_digest != expect_digest {
-                return false;
+            let actual_digest = match siginfo.typ {
+                0 => siginfo.pkcs.gen_digest_256(bin_all.as_slice())?,
+                1 => siginfo.pkcs.gen_digest_256(bin_crate)?,
+                _ => return Err(Error::Format("sig type is not right!".to_string())),
             };
+            let verification = PKCS::decode_pkcs_bin(
+                siginfo.bin.as_slice(),
+                &self.root_cas,
+                &self.intermediate_cas,
+            )?;
+            if actual_digest != verification.content {
+                return Err(Error::SignatureMismatch("digest not right".to_string()));
+            };
+            siginfo.signers = verification.signers;
         }
-        true
+        Ok(())
     }
 
     pub fn decode_from_crate_package(
         &mut self,
-        bin: &[u8],
-    ) -> Result<(CratePackage, StringTable), String> {
-        if !self.check_fingerprint(bin) {
-            return Err("fingerprint not right".to_string());
+        bin: &'a [u8],
+    ) -> Result<(CratePackage<'a>, StringTable), Error> {
+        let (crate_package, str_table) = self.decode_from_crate_package_unverified(bin)?;
+        self.check_sigs(&crate_package, bin)?;
+        Ok((crate_package, str_table))
+    }
+
+    ///like `decode_from_crate_package`, but signatures are read without being verified,
+    ///the fingerprint is still checked
+    pub fn decode_from_crate_package_unverified(
+        &mut self,
+        bin: &'a [u8],
+    ) -> Result<(CratePackage<'a>, StringTable), Error> {
+        if !bin.starts_with(&MAGIC_NUMBER) {
+            return Err(Error::BadMagic);
+        }
+        if !self.check_fingerprint(bin)? {
+            return Err(Error::FingerprintMismatch);
         }
         let crate_package = CratePackage::decode_from_slice(bin)?;
         let mut str_table = StringTable::new();
-        str_table.read_bytes(crate_package.string_table.arr.as_slice());
-        self.pack_info(&crate_package, &str_table);
-        self.deps(&crate_package, &str_table);
-        self.binary(&crate_package);
-        self.sigs(&crate_package);
-        if !self.check_sigs(&crate_package, bin) {
-            return Err("file sig not right".to_string());
-        }
+        str_table.read_bytes(&crate_package.string_table)?;
+        self.pack_info(&crate_package, &str_table)?;
+        self.deps(&crate_package, &str_table)?;
+        self.binary(&crate_package)?;
+        self.vcs_info(&crate_package, &str_table)?;
+        self.locked_packages(&crate_package, &str_table)?;
+        self.features(&crate_package, &str_table)?;
+        self.targets(&crate_package, &str_table)?;
+        self.unknown_sections(&crate_package);
+        self.sigs(&crate_package)?;
         Ok((crate_package, str_table))
     }
 }
 
 #[test]
 fn test_encode_decode() {
-    use crate::utils::context::{PackageInfo, SrcTypePath, SIGTYPE};
+    use crate::utils::context::{DepKind, GitRef, PackageInfo, SrcTypePath, SIGTYPE};
     fn pack_info() -> PackageInfo {
         PackageInfo {
             name: "rust-crate".to_string(),
             version: "1.0.0".to_string(),
             license: "MIT".to_string(),
             authors: vec!["shuibing".to_string(), "rust".to_string()],
+            description: "a crate for tests".to_string(),
+            repository: "https://github.com/open-rust-initiative/cargo-crate".to_string(),
+            keywords: vec!["crate".to_string(), "sign".to_string()],
+            categories: vec!["cryptography".to_string()],
+            edition: "2021".to_string(),
+            rust_version: "1.70".to_string(),
+            ..Default::default()
         }
     }
 
@@ -188,7 +416,8 @@ fn test_encode_decode() {
             ver_req: "1.0.0".to_string(),
             src: SrcTypePath::CratesIo,
             src_platform: "ALL".to_string(),
-            dump: true,
+            kind: DepKind::Normal,
+            ..Default::default()
         }
     }
 
@@ -198,6 +427,12 @@ fn test_encode_decode() {
             ver_req: ">=0.8.0".to_string(),
             src: SrcTypePath::Git("http://git.com".to_string()),
             src_platform: "windows".to_string(),
+            kind: DepKind::Dev,
+            features: vec!["serde".to_string(), "alloc".to_string()],
+            optional: true,
+            default_features: false,
+            package: "crate-spec-impl".to_string(),
+            git_ref: GitRef::Tag("v0.8.0".to_string()),
             dump: true,
         }
     }
@@ -208,11 +443,13 @@ fn test_encode_decode() {
 
     fn sign() -> PKCS {
         let mut pkcs1 = PKCS::new();
-        pkcs1.load_from_file_writer(
-            "test/cert.pem".to_string(),
-            "test/key.pem".to_string(),
-            ["test/root-ca.pem".to_string()].to_vec(),
-        );
+        pkcs1
+            .load_from_file_writer(
+                "test/cert.pem".to_string(),
+                "test/key.pem".to_string(),
+                ["test/root-ca.pem".to_string()].to_vec(),
+            )
+            .unwrap();
         pkcs1
     }
 
@@ -221,16 +458,15 @@ fn test_encode_decode() {
     package_context.pack_info = pack_info();
     package_context.dep_infos.push(dep_info1());
     package_context.dep_infos.push(dep_info2());
-    package_context.crate_binary.bytes = crate_binary();
+    package_context.add_crate_bin(crate_binary());
     package_context.add_sig(sign(), SIGTYPE::CRATEBIN);
     package_context.add_sig(sign(), SIGTYPE::FILE);
 
-    let (_crate_package, _str_table, bin) = package_context.encode_to_crate_package();
+    let (_crate_package, _str_table, bin) = package_context.encode_to_crate_package().unwrap();
 
     let mut package_context_new = PackageContext::new();
-    package_context_new.set_root_cas_bin(PKCS::root_ca_bins(
-        ["test/root-ca.pem".to_string()].to_vec(),
-    ));
+    package_context_new
+        .set_root_cas_bin(PKCS::root_ca_bins(["test/root-ca.pem".to_string()].to_vec()).unwrap());
     let (_crate_package_new, _str_table) = package_context_new
         .decode_from_crate_package(bin.as_slice())
         .unwrap();
@@ -238,5 +474,330 @@ fn test_encode_decode() {
     assert_eq!(pack_info(), package_context_new.pack_info);
     assert_eq!(dep_info1(), package_context_new.dep_infos[0]);
     assert_eq!(dep_info2(), package_context_new.dep_infos[1]);
-    assert_eq!(crate_binary(), package_context_new.crate_binary.bytes);
+    assert_eq!(crate_binary(), *package_context_new.crate_binary.bytes);
+}
+
+#[test]
+fn test_decode_tampered() {
+    use crate::utils::context::{PackageInfo, SIGTYPE};
+
+    fn sign() -> PKCS {
+        let mut pkcs1 = PKCS::new();
+        pkcs1
+            .load_from_file_writer(
+                "test/cert.pem".to_string(),
+                "test/key.pem".to_string(),
+                ["test/root-ca.pem".to_string()].to_vec(),
+            )
+            .unwrap();
+        pkcs1
+    }
+
+    fn decode(bin: &[u8], root_ca_paths: Vec<String>) -> Result<(), Error> {
+        let mut package_context = PackageContext::new();
+        package_context.set_root_cas_bin(PKCS::root_ca_bins(root_ca_paths)?);
+        package_context.decode_from_crate_package(bin).map(|_| ())
+    }
+
+    let mut package_context = PackageContext::new();
+    package_context.pack_info = PackageInfo::new(
+        "rust-crate".to_string(),
+        "1.0.0".to_string(),
+        "MIT".to_string(),
+        vec![],
+    );
+    package_context.add_crate_bin([15; 100].to_vec());
+    package_context.add_sig(sign(), SIGTYPE::CRATEBIN);
+    let (_crate_package, _str_table, bin) = package_context.encode_to_crate_package().unwrap();
+    let root_cas = vec!["test/root-ca.pem".to_string()];
+    assert!(decode(&bin, root_cas.clone()).is_ok());
+
+    let mut bad_magic = bin.clone();
+    bad_magic[0] = 0;
+    assert!(matches!(
+        decode(&bad_magic, root_cas.clone()),
+        Err(Error::BadMagic)
+    ));
+
+    //flip one byte of the crate binary
+    let crate_bin_off = bin.windows(100).position(|w| w == [15; 100]).unwrap();
+    let mut tampered = bin.clone();
+    tampered[crate_bin_off] = 16;
+    assert!(matches!(
+        decode(&tampered, root_cas.clone()),
+        Err(Error::FingerprintMismatch)
+    ));
+
+    //fix up the fingerprint as well, only the signature can tell now
+    let fp_off = tampered.len() - FINGERPRINT_LEN;
+    let fp = PKCS::new().gen_digest_256(&tampered[..fp_off]).unwrap();
+    tampered[fp_off..].copy_from_slice(fp.as_slice());
+    assert!(matches!(
+        decode(&tampered, root_cas),
+        Err(Error::SignatureMismatch(_))
+    ));
+
+    assert!(matches!(
+        decode(&bin, vec!["test/cert.pem".to_string()]),
+        Err(Error::UntrustedSigner(_))
+    ));
+}
+
+#[test]
+fn test_decode_hostile() {
+    use crate::utils::context::{PackageInfo, SrcTypePath};
+    use crate::utils::package::CrateHeader;
+
+    fn decode(bin: &[u8]) -> Result<(), Error> {
+        PackageContext::new()
+            .decode_from_crate_package(bin)
+            .map(|_| ())
+    }
+
+    //recompute the fingerprint so the mutation reaches the structure parser
+    fn fix_fingerprint(bin: &mut [u8]) {
+        if bin.len() >= FINGERPRINT_LEN {
+            let fp_off = bin.len() - FINGERPRINT_LEN;
+            let fp = PKCS::new().gen_digest_256(&bin[..fp_off]).unwrap();
+            bin[fp_off..].copy_from_slice(fp.as_slice());
+        }
+    }
+
+    let mut package_context = PackageContext::new();
+    package_context.pack_info = PackageInfo::new(
+        "rust-crate".to_string(),
+        "1.0.0".to_string(),
+        "MIT".to_string(),
+        vec!["rust".to_string()],
+    );
+    package_context.add_dep_info(
+        "toml".to_string(),
+        "0.7.4".to_string(),
+        SrcTypePath::Git("http://git.com".to_string()),
+        "".to_string(),
+    );
+    package_context.add_crate_bin([15; 16].to_vec());
+    let (crate_package, _str_table, bin) = package_context.encode_to_crate_package().unwrap();
+    assert!(decode(&bin).is_ok());
+
+    //the string table, section index and data sections must follow each other,
+    //moving any of them by the alignment is rejected even with the bytes in place
+    let step = crate_package.layout().alignment() as u64;
+    let header = &crate_package.crate_header;
+    let moves: [fn(&mut CrateHeader, u64); 8] = [
+        |h, step| h.strtable_offset += step,
+        |h, step| h.strtable_offset -= step,
+        |h, step| {
+            h.strtable_offset += step;
+            h.strtable_size -= step;
+        },
+        |h, step| h.si_offset += step,
+        |h, step| h.si_offset -= step,
+        |h, step| h.ds_offset += step,
+        |h, step| h.ds_offset -= step,
+        |h, step| {
+            h.si_offset += step;
+            h.ds_offset += step;
+        },
+    ];
+    for move_header in moves {
+        let mut moved = header.clone();
+        move_header(&mut moved, step);
+        let mut mutated = bin.clone();
+        let header_bin = encode2vec_by_bincode(&moved);
+        mutated[MAGIC_NUMBER.len()..MAGIC_NUMBER.len() + header_bin.len()]
+            .copy_from_slice(&header_bin);
+        fix_fingerprint(&mut mutated);
+        assert!(matches!(
+            CratePackage::decode_from_slice(&mutated),
+            Err(Error::Format(_))
+        ));
+        assert!(decode(&mutated).is_err());
+    }
+
+    for len in 0..bin.len() {
+        let mut truncated = bin[..len].to_vec();
+        assert!(CratePackage::decode_from_slice(&truncated).is_err());
+        fix_fingerprint(&mut truncated);
+        assert!(decode(&truncated).is_err());
+    }
+
+    for i in 0..bin.len() - FINGERPRINT_LEN {
+        for val in [0x00, 0x7f, 0xff] {
+            let mut mutated = bin.clone();
+            mutated[i] = val;
+            let _ = CratePackage::decode_from_slice(&mutated);
+            fix_fingerprint(&mut mutated);
+            let _ = decode(&mutated);
+        }
+    }
+}
+
+#[test]
+fn test_unknown_sections() {
+    use crate::utils::context::PackageInfo;
+    use crate::utils::package::{UnknownSection, SECTION_NONCRITICAL};
+
+    fn package_with(sh_type: u8) -> Vec<u8> {
+        let mut package_context = PackageContext::new();
+        package_context.pack_info = PackageInfo::new(
+            "rust-crate".to_string(),
+            "1.0.0".to_string(),
+            "MIT".to_string(),
+            vec!["rust".to_string()],
+        );
+        package_context.add_crate_bin([15; 16].to_vec());
+        package_context.unknown_sections = vec![UnknownSection {
+            sh_type,
+            bin: vec![1, 2, 3, 4, 5].into(),
+        }];
+        let (_, _, bin) = package_context.encode_to_crate_package().unwrap();
+        bin
+    }
+
+    //a non-critical section from a newer writer is kept byte-for-byte
+    let bin = package_with(42 | SECTION_NONCRITICAL);
+    let mut package_context = PackageContext::new();
+    package_context
+        .decode_from_crate_package_unverified(&bin)
+        .unwrap();
+    assert_eq!(package_context.unknown_sections.len(), 1);
+    assert_eq!(*package_context.unknown_sections[0].bin, [1, 2, 3, 4, 5]);
+    let (_, _, reencoded) = package_context.encode_to_crate_package().unwrap();
+    assert_eq!(reencoded, bin);
+
+    //an unknown critical section can't be skipped
+    let bin = package_with(42);
+    assert!(matches!(
+        PackageContext::new().decode_from_crate_package_unverified(&bin),
+        Err(Error::Format(_))
+    ));
+}
+
+#[test]
+fn test_wide_offsets() {
+    use crate::utils::context::{PackageInfo, TargetInfo, SIGTYPE};
+    use crate::utils::package::{has_wide_offsets, CRATEVERSION, C_VERSION_WIDE};
+
+    let mut package_context = PackageContext::new();
+    package_context.pack_info = PackageInfo::new(
+        "rust-crate".to_string(),
+        "1.0.0".to_string(),
+        "MIT".to_string(),
+        vec!["rust".to_string()],
+    );
+    package_context.add_crate_bin([15; 100].to_vec());
+    package_context
+        .features
+        .insert("default".to_string(), vec!["std".to_string()]);
+    package_context.targets = vec![TargetInfo {
+        name: "rust-crate".to_string(),
+        kind: vec!["bin".to_string()],
+        src_path: "src/main.rs".to_string(),
+    }];
+    let mut pkcs = PKCS::new();
+    pkcs.load_from_file_writer(
+        "test/cert.pem".to_string(),
+        "test/key.pem".to_string(),
+        ["test/root-ca.pem".to_string()].to_vec(),
+    )
+    .unwrap();
+    package_context.add_sig(pkcs, SIGTYPE::CRATEBIN);
+    let (mut crate_package, _, compact) = package_context.encode_to_crate_package().unwrap();
+    //small packages get 4-byte offsets
+    assert_eq!(crate_package.crate_header.c_version, CRATEVERSION);
+    assert_eq!(crate_package.encoded_size(), compact.len());
+
+    //the same package laid out with 8-byte ones
+    crate_package.crate_header.c_version = CRATEVERSION | C_VERSION_WIDE;
+    crate_package.set_section_index();
+    crate_package.set_crate_header(0).unwrap();
+    let mut wide = crate_package.encode_to_vec();
+    assert_eq!(crate_package.encoded_size(), wide.len());
+    let fp_off = wide.len() - FINGERPRINT_LEN;
+    let fp = PKCS::new().gen_digest_256(&wide[..fp_off]).unwrap();
+    wide[fp_off..].copy_from_slice(fp.as_slice());
+    assert!(has_wide_offsets(wide[MAGIC_NUMBER.len()]));
+    //every data section starts 8-aligned
+    let ds_offset = crate_package.crate_header.ds_offset;
+    assert!(crate_package
+        .section_index
+        .entries
+        .arr
+        .iter()
+        .all(|entry| (ds_offset + entry.sh_offset).is_multiple_of(8)));
+
+    let mut package_context_new = PackageContext::new();
+    package_context_new
+        .set_root_cas_bin(PKCS::root_ca_bins(["test/root-ca.pem".to_string()].to_vec()).unwrap());
+    package_context_new
+        .decode_from_crate_package(&wide)
+        .unwrap();
+    assert_eq!(package_context_new.pack_info, package_context.pack_info);
+    assert_eq!(package_context_new.features, package_context.features);
+    assert_eq!(package_context_new.targets, package_context.targets);
+    assert_eq!(*package_context_new.crate_binary.bytes, [15; 100]);
+
+    //re-encoding picks the compact form again
+    let (_, _, reencoded) = package_context_new.encode_to_crate_package().unwrap();
+    assert_eq!(reencoded, compact);
+}
+
+#[test]
+fn test_aligned_layout() {
+    use crate::utils::context::PackageInfo;
+
+    fn decode(bin: &[u8]) -> Result<(), Error> {
+        PackageContext::new()
+            .decode_from_crate_package_unverified(bin)
+            .map(|_| ())
+    }
+
+    fn with_fingerprint(mut bin: Vec<u8>) -> Vec<u8> {
+        let fp_off = bin.len() - FINGERPRINT_LEN;
+        let fp = PKCS::new().gen_digest_256(&bin[..fp_off]).unwrap();
+        bin[fp_off..].copy_from_slice(fp.as_slice());
+        bin
+    }
+
+    let mut package_context = PackageContext::new();
+    package_context.pack_info = PackageInfo::new(
+        "rust-crate".to_string(),
+        "1.0.0".to_string(),
+        "MIT".to_string(),
+        vec![],
+    );
+    //13 bytes, the features section after it needs 3 bytes of padding
+    package_context.add_crate_bin([15; 13].to_vec());
+    package_context
+        .features
+        .insert("default".to_string(), vec![]);
+    let (crate_package, _, bin) = package_context.encode_to_crate_package().unwrap();
+    assert!(decode(&bin).is_ok());
+
+    let header = &crate_package.crate_header;
+    assert_eq!(header.strtable_offset, 32);
+    assert!(header.si_offset.is_multiple_of(4) && header.ds_offset.is_multiple_of(4));
+    let entries = &crate_package.section_index.entries.arr;
+    assert_eq!(header.si_size, entries.len() as Size * 12);
+    assert!(entries
+        .iter()
+        .all(|entry| entry.sh_offset.is_multiple_of(4)));
+
+    //padding is zero, in the header and between sections
+    let mut bad = bin.clone();
+    bad[MAGIC_NUMBER.len() + 1] = 1;
+    assert!(matches!(
+        decode(&with_fingerprint(bad)),
+        Err(Error::Format(_))
+    ));
+    let crate_bin = &entries[2];
+    let gap = (header.ds_offset + crate_bin.sh_offset + crate_bin.sh_size) as usize;
+    assert_eq!(bin[gap..gap + 3], [0, 0, 0]);
+    let mut bad = bin.clone();
+    bad[gap] = 1;
+    assert!(matches!(
+        decode(&with_fingerprint(bad)),
+        Err(Error::Format(_))
+    ));
 }
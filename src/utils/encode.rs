@@ -1,17 +1,29 @@
-use crate::utils::context::{PackageContext, StringTable, NOT_SIG_NUM};
+use crate::utils::context::{
+    write_to_features_section, write_to_targets_section, PackageContext, StringTable, SIGTYPE,
+};
+use crate::utils::error::Error;
 use crate::utils::package::{
-    datasection_type, CrateBinarySection, CratePackage, DataSection, DataSectionCollectionType,
-    DepTableEntry, DepTableSection, LenArrayType, Off, PackageSection, RawArrayType,
-    SectionIndexEntry, SigStructureSection, Size, CRATEVERSION, FINGERPRINT_LEN, MAGIC_NUMBER,
+    datasection_type, has_wide_offsets, CrateBinarySection, CratePackage, DataSection,
+    DataSectionCollectionType, DepTableEntry, DepTableSection, FeaturesSection, LenArrayType,
+    LockfileEntry, LockfileSection, Off, PackageSection, SectionIndexEntry, SigStructureSection,
+    Size, TargetsSection, UnknownSection, VcsInfoSection, C_VERSION_WIDE, FINGERPRINT_LEN,
+    MAGIC_NUMBER,
 };
 
-use crate::utils::package::gen_bincode::{encode2vec_by_bincode, encode_size_by_bincode};
+use crate::utils::package::gen_bincode::encode2vec_by_bincode;
 use crate::utils::pkcs::PKCS;
+use std::borrow::Cow;
 
-impl CratePackage {
+impl CratePackage<'_> {
     pub fn set_section_index(&mut self) {
         self.section_index.entries.arr = vec![];
-        for (i, (_size, _off)) in self.data_sections.encode_size_offset().iter().enumerate() {
+        let layout = self.layout();
+        for (i, (_size, _off)) in self
+            .data_sections
+            .encode_size_offset(layout)
+            .iter()
+            .enumerate()
+        {
             let size = *_size;
             let off = *_off;
             let typ = datasection_type(&self.data_sections.col.arr[i]);
@@ -24,20 +36,47 @@ impl CratePackage {
     }
 
     pub fn set_string_table(&mut self, str_table: &StringTable) {
-        self.string_table = RawArrayType::from_vec(str_table.to_bytes());
+        self.string_table = Cow::Owned(str_table.to_bytes());
     }
 
-    pub fn set_crate_header(&mut self, fake_num: usize) {
-        self.crate_header.c_version = CRATEVERSION;
-        self.crate_header.strtable_size = self.string_table.size() as Size;
-        self.crate_header.strtable_offset =
-            (self.crate_header.size() + self.magic_number.len()) as Size;
-        self.crate_header.si_size = self.section_index.size() as Size
-            + (fake_num * SectionIndexEntry::default().size()) as Size;
-        self.crate_header.si_num = self.section_index.num() as Size + fake_num as Size;
-        self.crate_header.si_offset =
-            self.crate_header.strtable_offset + self.crate_header.strtable_size;
-        self.crate_header.ds_offset = self.crate_header.si_offset + self.crate_header.si_size;
+    ///lay out the header in the offset width and alignment its c_version says,
+    ///errors if the package doesn't fit it
+    pub fn set_crate_header(&mut self, fake_num: usize) -> Result<(), Error> {
+        let err = || Error::Format("package too large for its offset width".to_string());
+        let layout = self.layout();
+        let header = &mut self.crate_header;
+        header.strtable_size = self.string_table.len() as Size;
+        header.strtable_offset = (header.size() + self.magic_number.len()) as Off;
+        header.si_size = fake_num
+            .checked_mul(SectionIndexEntry::default().size(layout))
+            .and_then(|fake_size| fake_size.checked_add(self.section_index.size(layout)))
+            .ok_or_else(err)? as Size;
+        header.si_num = (self.section_index.num() + fake_num) as Size;
+        header.si_offset = layout.align_up(
+            header
+                .strtable_offset
+                .checked_add(header.strtable_size)
+                .ok_or_else(err)? as usize,
+        ) as Off;
+        header.ds_offset = header
+            .si_offset
+            .checked_add(header.si_size)
+            .ok_or_else(err)?;
+        let end = header
+            .ds_offset
+            .checked_add(self.data_sections.size(layout) as Size)
+            .ok_or_else(err)?;
+        if !has_wide_offsets(header.c_version) && end > u32::MAX as Size {
+            return Err(err());
+        }
+        Ok(())
+    }
+
+    ///length of the encoded package, as laid out by `set_crate_header`
+    pub fn encoded_size(&self) -> usize {
+        self.crate_header.ds_offset as usize
+            + self.data_sections.size(self.layout())
+            + FINGERPRINT_LEN
     }
 
     pub fn set_magic_number(&mut self) {
@@ -49,20 +88,24 @@ impl CratePackage {
     }
 }
 
-impl PackageContext {
+///room left for each signature still to be made when picking the offset width,
+///PKCS#7 structures with their certificates are far below it
+const SIG_SIZE_BOUND: usize = 1 << 20;
+
+impl<'a> PackageContext<'a> {
     fn write_to_data_section_collection_without_sig(
         &self,
-        dsc: &mut DataSectionCollectionType,
+        dsc: &mut DataSectionCollectionType<'a>,
         str_table: &mut StringTable,
-    ) {
+    ) -> Result<(), Error> {
         let mut package_section = PackageSection::new();
-        self.write_to_package_section(&mut package_section, str_table);
+        self.write_to_package_section(&mut package_section, str_table)?;
         dsc.col
             .arr
             .push(DataSection::PackageSection(package_section));
 
         let mut dep_table_section = DepTableSection::new();
-        self.write_to_dep_table_section(&mut dep_table_section, str_table);
+        self.write_to_dep_table_section(&mut dep_table_section, str_table)?;
         dsc.col
             .arr
             .push(DataSection::DepTableSection(dep_table_section));
@@ -72,6 +115,51 @@ impl PackageContext {
         dsc.col
             .arr
             .push(DataSection::CrateBinarySection(binary_section));
+
+        if let Some(vcs_info) = &self.vcs_info {
+            let mut vcs_info_section = VcsInfoSection::new();
+            vcs_info.write_to_vcs_info_section(&mut vcs_info_section, str_table)?;
+            dsc.col
+                .arr
+                .push(DataSection::VcsInfoSection(vcs_info_section));
+        }
+
+        if !self.locked_packages.is_empty() {
+            let mut lockfile_section = LockfileSection::new();
+            for locked in self.locked_packages.iter() {
+                let mut entry = LockfileEntry::default();
+                locked.write_to_lockfile_entry(&mut entry, str_table)?;
+                lockfile_section.entries.arr.push(entry);
+            }
+            lockfile_section.entries.len = lockfile_section.entries.arr.len() as Size;
+            dsc.col
+                .arr
+                .push(DataSection::LockfileSection(lockfile_section));
+        }
+
+        if !self.features.is_empty() {
+            let mut features_section = FeaturesSection::new();
+            write_to_features_section(&self.features, &mut features_section, str_table)?;
+            dsc.col
+                .arr
+                .push(DataSection::FeaturesSection(features_section));
+        }
+
+        if !self.targets.is_empty() {
+            let mut targets_section = TargetsSection::new();
+            write_to_targets_section(&self.targets, &mut targets_section, str_table)?;
+            dsc.col
+                .arr
+                .push(DataSection::TargetsSection(targets_section));
+        }
+
+        for unknown in self.unknown_sections.iter() {
+            dsc.col.arr.push(DataSection::Unknown(UnknownSection {
+                sh_type: unknown.sh_type,
+                bin: unknown.bin.clone(),
+            }));
+        }
+        Ok(())
     }
 
     pub fn write_to_data_section_collection_sig(&self, dsc: &mut DataSectionCollectionType) {
@@ -82,101 +170,150 @@ impl PackageContext {
         }
     }
 
-    fn write_to_package_section(&self, ps: &mut PackageSection, str_table: &mut StringTable) {
-        self.pack_info.write_to_package_section(ps, str_table);
-        encode_size_by_bincode(ps);
+    fn write_to_package_section(
+        &self,
+        ps: &mut PackageSection,
+        str_table: &mut StringTable,
+    ) -> Result<(), Error> {
+        self.pack_info.write_to_package_section(ps, str_table)
     }
 
-    fn write_to_dep_table_section(&self, dts: &mut DepTableSection, str_table: &mut StringTable) {
+    fn write_to_dep_table_section(
+        &self,
+        dts: &mut DepTableSection,
+        str_table: &mut StringTable,
+    ) -> Result<(), Error> {
         let mut entries = vec![];
-        self.dep_infos.iter().for_each(|dep_info| {
+        for dep_info in self.dep_infos.iter() {
             let mut dte = DepTableEntry::new();
-            dep_info.write_to_dep_table_entry(&mut dte, str_table);
+            dep_info.write_to_dep_table_entry(&mut dte, str_table)?;
             entries.push(dte);
-        });
+        }
         dts.entries = LenArrayType::from_vec(entries);
+        Ok(())
     }
 
-    fn write_to_crate_binary_section(&self, cbs: &mut CrateBinarySection) {
+    fn write_to_crate_binary_section(&self, cbs: &mut CrateBinarySection<'a>) {
         self.crate_binary.write_to_crate_binary_section(cbs);
     }
-    fn set_sigs(&self, crate_package: &mut CratePackage, non_sig_num: usize) {
-        crate_package.data_sections.col.arr.truncate(non_sig_num);
+
+    fn set_sigs(&self, crate_package: &mut CratePackage) {
+        //signature sections are always the last ones
+        crate_package
+            .data_sections
+            .col
+            .arr
+            .retain(|section| !matches!(section, DataSection::SigStructureSection(_)));
         self.write_to_data_section_collection_sig(&mut crate_package.data_sections);
     }
 
-    fn set_pack_dep_bin(&self, crate_package: &mut CratePackage, str_table: &mut StringTable) {
+    fn set_pack_dep_bin(
+        &self,
+        crate_package: &mut CratePackage<'a>,
+        str_table: &mut StringTable,
+    ) -> Result<(), Error> {
         self.write_to_data_section_collection_without_sig(
             &mut crate_package.data_sections,
             str_table,
-        );
-    }
-
-    fn calc_sigs(&mut self, crate_package: &CratePackage) {
-        let bin_all = encode2vec_by_bincode(crate_package);
-        let bin_all = self.binary_before_sig(crate_package, bin_all.as_slice());
-        let bin_crate = crate_package.crate_binary_section().bin.arr.as_slice();
-        self.sigs.iter_mut().for_each(|siginfo| {
-            let digest;
-            match siginfo.typ {
-                0 => {
-                    digest = siginfo.pkcs.gen_digest_256(bin_all.as_slice());
-                }
-                1 => {
-                    digest = siginfo.pkcs.gen_digest_256(bin_crate);
-                }
-                _ => {
-                    panic!("sig type is not right!")
-                }
-            }
-            siginfo.bin = siginfo.pkcs.encode_pkcs_bin(digest.as_slice());
-            siginfo.size = siginfo.bin.len();
-        });
+        )
     }
 
-    fn calc_fingerprint(&self, crate_package: &CratePackage) -> Vec<u8> {
-        let bin_all = encode2vec_by_bincode(crate_package);
-        PKCS::new().gen_digest_256(&bin_all[..bin_all.len() - FINGERPRINT_LEN])
+    fn calc_sigs(&mut self, crate_package: &CratePackage) -> Result<(), Error> {
+        //the package is only encoded here if a new file signature covers it
+        let bin_all = if self
+            .sigs
+            .iter()
+            .any(|sig| sig.bin.is_empty() && sig.typ == SIGTYPE::FILE.typ())
+        {
+            let bin_all = encode2vec_by_bincode(crate_package);
+            self.binary_before_sig(crate_package, bin_all.as_slice())?
+        } else {
+            vec![]
+        };
+        let bin_crate = &*crate_package.crate_binary_section()?.bin;
+        for siginfo in self.sigs.iter_mut() {
+            //signatures read from an existing package are kept as they are
+            if !siginfo.bin.is_empty() {
+                continue;
+            }
+            let digest = match siginfo.typ {
+                0 => siginfo.pkcs.gen_digest_256(bin_all.as_slice())?,
+                1 => siginfo.pkcs.gen_digest_256(bin_crate)?,
+                _ => return Err(Error::Format("sig type is not right!".to_string())),
+            };
+            siginfo.bin = siginfo.pkcs.encode_pkcs_bin(digest.as_slice())?;
+            siginfo.size = siginfo.bin.len();
+        }
+        Ok(())
     }
 
     //1 before sig
     fn encode_to_crate_package_before_sig(
         &self,
         str_table: &mut StringTable,
-        crate_package: &mut CratePackage,
-    ) {
+        crate_package: &mut CratePackage<'a>,
+    ) -> Result<(), Error> {
         crate_package.set_magic_number();
-        self.set_pack_dep_bin(crate_package, str_table);
+        self.set_pack_dep_bin(crate_package, str_table)?;
         //this is setting fake sigsection
-        self.set_sigs(crate_package, NOT_SIG_NUM);
+        self.set_sigs(crate_package);
         crate_package.set_section_index();
         crate_package.set_string_table(str_table);
-        crate_package.set_crate_header(0);
+        crate_package.set_crate_header(0)
     }
 
     //2 sig
-    fn encode_sig_to_crate_package(&mut self, crate_package: &mut CratePackage) {
-        self.calc_sigs(crate_package);
+    fn encode_sig_to_crate_package(
+        &mut self,
+        crate_package: &mut CratePackage,
+    ) -> Result<(), Error> {
+        self.calc_sigs(crate_package)?;
         //this is setting true sigsection
-        self.set_sigs(crate_package, NOT_SIG_NUM);
+        self.set_sigs(crate_package);
+        Ok(())
     }
 
     //3 after sig
-    fn encode_to_crate_package_after_sig(&self, crate_package: &mut CratePackage) {
+    fn encode_to_crate_package_after_sig(
+        &self,
+        crate_package: &mut CratePackage,
+    ) -> Result<Vec<u8>, Error> {
         crate_package.set_section_index();
-        crate_package.set_crate_header(0);
-        let finger_print = self.calc_fingerprint(crate_package);
+        crate_package.set_crate_header(0)?;
+        //the fingerprint covers everything before it, fill it into the encoded bytes
+        let mut bin = encode2vec_by_bincode(crate_package);
+        let fp_off = bin.len() - FINGERPRINT_LEN;
+        let finger_print = PKCS::new().gen_digest_256(&bin[..fp_off])?;
+        bin[fp_off..].copy_from_slice(finger_print.as_slice());
         crate_package.set_finger_print(finger_print);
+        Ok(bin)
     }
 
     //1 2 3
-    pub fn encode_to_crate_package(&mut self) -> (CratePackage, StringTable, Vec<u8>) {
+    ///the crate binary and unknown sections of the returned package are borrowed
+    ///like the context's, so re-encoding a decoded package doesn't copy them
+    pub fn encode_to_crate_package(
+        &mut self,
+    ) -> Result<(CratePackage<'a>, StringTable, Vec<u8>), Error> {
         let mut crate_package = CratePackage::new();
         let mut str_table = StringTable::new();
-        self.encode_to_crate_package_before_sig(&mut str_table, &mut crate_package);
-        self.encode_sig_to_crate_package(&mut crate_package);
-        self.encode_to_crate_package_after_sig(&mut crate_package);
-        let bin = encode2vec_by_bincode(&crate_package);
-        (crate_package, str_table, bin)
+        //strings of a decoded package keep their offsets, new ones are appended
+        if !self.base_str_table.is_empty() {
+            str_table.read_bytes(&self.base_str_table)?;
+        }
+        //lay out with 8-byte offsets first, it always fits, then switch to
+        //4-byte ones if the signed package will fit those
+        let c_version = crate_package.crate_header.c_version;
+        crate_package.crate_header.c_version = c_version | C_VERSION_WIDE;
+        self.encode_to_crate_package_before_sig(&mut str_table, &mut crate_package)?;
+        let sig_bound = self.sigs.len() * SIG_SIZE_BOUND;
+        if crate_package.encoded_size() + sig_bound <= u32::MAX as usize {
+            crate_package.crate_header.c_version = c_version;
+        }
+        crate_package.set_section_index();
+        crate_package.set_crate_header(0)?;
+        self.encode_sig_to_crate_package(&mut crate_package)?;
+        let bin = self.encode_to_crate_package_after_sig(&mut crate_package)?;
+        Ok((crate_package, str_table, bin))
     }
 }
@@ -0,0 +1,93 @@
+use crate::utils::package::CRATEVERSION;
+use openssl::error::ErrorStack;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+///errors raised while packing, signing, decoding and verifying a `.scrate` package
+#[derive(Debug)]
+pub enum Error {
+    ///the file doesn't start with the `.scrate` magic number
+    BadMagic,
+    ///the file structure can't be parsed
+    Format(String),
+    ///the header's `c_version` is newer than this version can read
+    UnsupportedVersion(u8),
+    ///the trailing fingerprint doesn't match the file content
+    FingerprintMismatch,
+    ///a signature is broken or doesn't match the content it covers
+    SignatureMismatch(String),
+    ///a signer certificate doesn't chain to any trusted root CA
+    UntrustedSigner(String),
+    ///reading or writing a file failed
+    Io(io::Error),
+    ///a toml document can't be parsed
+    Toml(toml::de::Error),
+    ///the Cargo.toml is valid toml but not a valid manifest
+    Manifest(String),
+    ///metadata can't be serialized
+    Serialize(String),
+    ///certificate, key or PKCS#7 handling failed
+    OpenSsl(ErrorStack),
+    ///running cargo failed
+    Cargo(String),
+    ///a command line argument doesn't fit the package, e.g. a signature index out of range
+    Argument(String),
+    ///(failed, total) packages of a batch failed, each failure was reported as it happened
+    Batch(usize, usize),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::BadMagic => write!(f, "magic not right!"),
+            Error::Format(msg) => write!(f, "{}", msg),
+            Error::UnsupportedVersion(version) => write!(
+                f,
+                "format version {} is not supported, this cargo-crate reads up to {}, update it",
+                version, CRATEVERSION
+            ),
+            Error::FingerprintMismatch => write!(f, "fingerprint not right"),
+            Error::SignatureMismatch(msg) => write!(f, "file sig not right: {}", msg),
+            Error::UntrustedSigner(msg) => write!(f, "signer not trusted: {}", msg),
+            Error::Io(err) => write!(f, "io error: {}", err),
+            Error::Toml(err) => write!(f, "toml error: {}", err),
+            Error::Manifest(msg) => write!(f, "manifest not right: {}", msg),
+            Error::Serialize(msg) => write!(f, "serialize error: {}", msg),
+            Error::OpenSsl(err) => write!(f, "openssl error: {}", err),
+            Error::Cargo(msg) => write!(f, "cargo failed: {}", msg),
+            Error::Argument(msg) => write!(f, "invalid argument: {}", msg),
+            Error::Batch(failed, total) => write!(f, "{} of {} package(s) failed", failed, total),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Toml(err) => Some(err),
+            Error::OpenSsl(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Self {
+        Error::Toml(err)
+    }
+}
+
+impl From<ErrorStack> for Error {
+    fn from(err: ErrorStack) -> Self {
+        Error::OpenSsl(err)
+    }
+}
@@ -0,0 +1,185 @@
+use crate::utils::context::LockedPackage;
+use crate::utils::error::Error;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+
+///the subset of Cargo.lock we read, any lockfile version
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockPackage>,
+    ///v1 keeps checksums here, as `"checksum <name> <version> (<source>)" = "<hex>"`
+    #[serde(default)]
+    metadata: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+struct LockPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+    checksum: Option<String>,
+    ///`name`, `name version` or `name version (source)`
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+pub struct CrateLock {
+    packages: Vec<LockPackage>,
+}
+
+impl CrateLock {
+    pub fn from_vec(st_vec: Vec<u8>) -> Result<CrateLock, Error> {
+        let st = String::from_utf8(st_vec)
+            .map_err(|_| Error::Manifest("Cargo.lock is not valid utf-8".to_string()))?;
+        let mut lockfile: Lockfile = toml::from_str(st.as_str())
+            .map_err(|e| Error::Manifest(format!("Cargo.lock not right: {}", e)))?;
+        for package in lockfile.package.iter_mut() {
+            let Some(source) = &package.source else {
+                continue;
+            };
+            let key = format!("checksum {} {} ({})", package.name, package.version, source);
+            //`<none>` marks a source without checksums
+            if let Some(checksum) = lockfile.metadata.get(&key).and_then(|val| val.as_str()) {
+                if package.checksum.is_none() && checksum != "<none>" {
+                    package.checksum = Some(checksum.to_string());
+                }
+            }
+        }
+        Ok(CrateLock {
+            packages: lockfile.package,
+        })
+    }
+
+    ///everything `name` `version` depends on, directly or not, in Cargo.lock's order;
+    ///a workspace lockfile also pins other members' dependencies, they are left out
+    pub fn resolved_deps(&self, name: &str, version: &str) -> Result<Vec<LockedPackage>, Error> {
+        let root = self
+            .packages
+            .iter()
+            .position(|package| {
+                package.name == name && package.version == version && package.source.is_none()
+            })
+            .ok_or_else(|| {
+                Error::Manifest(format!("Cargo.lock has no package {} {}", name, version))
+            })?;
+        let mut reached = HashSet::from([root]);
+        let mut pending = vec![root];
+        while let Some(i) = pending.pop() {
+            for dep in self.packages[i].dependencies.iter() {
+                let dep = self.find(dep)?;
+                if reached.insert(dep) {
+                    pending.push(dep);
+                }
+            }
+        }
+        Ok(self
+            .packages
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != root && reached.contains(i))
+            .map(|(_, package)| LockedPackage {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                source: package.source.clone().unwrap_or_default(),
+                checksum: package.checksum.clone().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    ///index of the package a `dependencies` entry refers to
+    fn find(&self, dep: &str) -> Result<usize, Error> {
+        let mut parts = dep.splitn(3, ' ');
+        let name = parts.next().unwrap_or_default();
+        let version = parts.next();
+        let source = parts
+            .next()
+            .map(|source| source.trim_start_matches('(').trim_end_matches(')'));
+        self.packages
+            .iter()
+            .position(|package| {
+                package.name == name
+                    && version.is_none_or(|version| package.version == version)
+                    && source.is_none_or(|source| package.source.as_deref() == Some(source))
+            })
+            .ok_or_else(|| Error::Manifest(format!("Cargo.lock has no package `{}`", dep)))
+    }
+}
+
+#[test]
+fn test_resolved_deps() {
+    let lock = r#"
+version = 4
+
+[[package]]
+name = "bar"
+version = "0.1.0"
+dependencies = ["rand"]
+
+[[package]]
+name = "foo"
+version = "0.3.0"
+dependencies = ["helper", "serde 1.0.200"]
+
+[[package]]
+name = "helper"
+version = "0.1.0"
+source = "git+https://example.com/helper?tag=v0.1#0123abcd"
+dependencies = ["serde 1.0.200 (registry+https://github.com/rust-lang/crates.io-index)"]
+
+[[package]]
+name = "rand"
+version = "0.8.5"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "34af8d1a0e25924bc5b7c43c079c942339d8f0a8b57c39049bef581b46327404"
+
+[[package]]
+name = "serde"
+version = "1.0.200"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "ddc6f9cc94d67c0e21aaf7eda3a010fd3af78ebf6e096aa6e2e13c79749cce4f"
+"#;
+    let lock = CrateLock::from_vec(lock.as_bytes().to_vec()).unwrap();
+    let deps = lock.resolved_deps("foo", "0.3.0").unwrap();
+    let names: Vec<_> = deps.iter().map(|dep| dep.name.as_str()).collect();
+    assert_eq!(names, ["helper", "serde"]);
+    assert!(deps[0].checksum.is_empty());
+    assert_eq!(deps[1].version, "1.0.200");
+    assert_eq!(deps[1].checksum.len(), 64);
+    assert!(lock.resolved_deps("foo", "0.4.0").is_err());
+}
+
+#[test]
+fn test_resolved_deps_v1() {
+    let lock = r#"
+[[package]]
+name = "foo"
+version = "0.3.0"
+dependencies = [
+ "helper 0.1.0 (git+https://example.com/helper?tag=v0.1#0123abcd)",
+ "serde 1.0.200 (registry+https://github.com/rust-lang/crates.io-index)",
+]
+
+[[package]]
+name = "helper"
+version = "0.1.0"
+source = "git+https://example.com/helper?tag=v0.1#0123abcd"
+
+[[package]]
+name = "serde"
+version = "1.0.200"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[metadata]
+"checksum helper 0.1.0 (git+https://example.com/helper?tag=v0.1#0123abcd)" = "<none>"
+"checksum serde 1.0.200 (registry+https://github.com/rust-lang/crates.io-index)" = "ddc6f9cc94d67c0e21aaf7eda3a010fd3af78ebf6e096aa6e2e13c79749cce4f"
+"#;
+    let lock = CrateLock::from_vec(lock.as_bytes().to_vec()).unwrap();
+    let deps = lock.resolved_deps("foo", "0.3.0").unwrap();
+    assert_eq!(deps.len(), 2);
+    assert!(deps[0].checksum.is_empty());
+    assert_eq!(
+        deps[1].checksum,
+        "ddc6f9cc94d67c0e21aaf7eda3a010fd3af78ebf6e096aa6e2e13c79749cce4f"
+    );
+}
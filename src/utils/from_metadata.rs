@@ -0,0 +1,383 @@
+use crate::utils::context::{
+    DepInfo, DepKind, GitRef, PackageContext, Skipped, SrcTypePath, TargetInfo,
+};
+use crate::utils::error::Error;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+///source id of crates.io in `cargo metadata`
+const CRATES_IO_SOURCE: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+///the subset of `cargo metadata --format-version 1` we read
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<MetaPackage>,
+    #[serde(default)]
+    workspace_members: Vec<String>,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct MetaPackage {
+    #[serde(default)]
+    id: String,
+    name: String,
+    version: String,
+    license: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    description: Option<String>,
+    documentation: Option<String>,
+    homepage: Option<String>,
+    repository: Option<String>,
+    readme: Option<String>,
+    #[serde(default)]
+    keywords: Vec<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+    #[serde(default)]
+    edition: String,
+    rust_version: Option<String>,
+    links: Option<String>,
+    license_file: Option<String>,
+    ///cargo already adds the implicit features of optional dependencies
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+    manifest_path: PathBuf,
+    dependencies: Vec<MetaDependency>,
+    #[serde(default)]
+    targets: Vec<MetaTarget>,
+    ///None: any registry, empty: `publish = false`
+    #[serde(default)]
+    publish: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct MetaTarget {
+    name: String,
+    kind: Vec<String>,
+    src_path: PathBuf,
+}
+
+///target kinds of a library, the others are `bin`, `example`, `test`, `bench` and `custom-build`
+const LIB_KINDS: [&str; 6] = ["lib", "rlib", "dylib", "cdylib", "staticlib", "proc-macro"];
+
+#[derive(Deserialize)]
+struct MetaDependency {
+    name: String,
+    source: Option<String>,
+    req: String,
+    kind: Option<String>,
+    rename: Option<String>,
+    optional: bool,
+    uses_default_features: bool,
+    features: Vec<String>,
+    target: Option<String>,
+    registry: Option<String>,
+    path: Option<PathBuf>,
+}
+
+///a member of a workspace
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkspaceMember {
+    pub name: String,
+    pub version: String,
+    pub manifest_dir: PathBuf,
+    ///false for `publish = false`
+    pub publish: bool,
+}
+
+///the workspace members listed by `cargo metadata`, in cargo's order
+pub fn workspace_members(json: &str) -> Result<Vec<WorkspaceMember>, Error> {
+    let metadata: Metadata = serde_json::from_str(json)
+        .map_err(|e| Error::Cargo(format!("cargo metadata output not right: {}", e)))?;
+    let mut members = vec![];
+    for id in metadata.workspace_members.iter() {
+        let package = metadata
+            .packages
+            .iter()
+            .find(|package| &package.id == id)
+            .ok_or_else(|| {
+                Error::Cargo(format!("workspace member {} not in cargo metadata", id))
+            })?;
+        members.push(WorkspaceMember {
+            name: package.name.clone(),
+            version: package.version.clone(),
+            manifest_dir: package
+                .manifest_path
+                .parent()
+                .unwrap_or(Path::new("."))
+                .to_path_buf(),
+            publish: package
+                .publish
+                .as_ref()
+                .is_none_or(|registries| !registries.is_empty()),
+        });
+    }
+    Ok(members)
+}
+
+///package metadata as resolved by cargo
+pub struct CrateMetadata {
+    package: MetaPackage,
+    target_directory: PathBuf,
+}
+
+impl CrateMetadata {
+    ///pick the package of `manifest_path` out of `cargo metadata` output
+    pub fn from_json(json: &str, manifest_path: &Path) -> Result<CrateMetadata, Error> {
+        let metadata: Metadata = serde_json::from_str(json)
+            .map_err(|e| Error::Cargo(format!("cargo metadata output not right: {}", e)))?;
+        let package = metadata
+            .packages
+            .into_iter()
+            .find(|package| package.manifest_path == manifest_path)
+            .ok_or_else(|| {
+                Error::Cargo(format!(
+                    "no package with manifest {} in cargo metadata",
+                    manifest_path.display()
+                ))
+            })?;
+        Ok(CrateMetadata {
+            package,
+            target_directory: metadata.target_directory,
+        })
+    }
+
+    pub fn target_directory(&self) -> &Path {
+        &self.target_directory
+    }
+
+    ///write package info and dependencies, returns the dependencies skipped because
+    ///cargo strips them when packaging (path-only dev-dependencies)
+    pub fn write_info_to_package_context(
+        &self,
+        package_context: &mut PackageContext,
+    ) -> Result<Vec<Skipped>, Error> {
+        let package = &self.package;
+        package_context.set_package_info(
+            package.name.clone(),
+            package.version.clone(),
+            package.license.clone().unwrap_or_default(),
+            package.authors.clone(),
+        );
+        let pack_info = &mut package_context.pack_info;
+        pack_info.description = package.description.clone().unwrap_or_default();
+        pack_info.documentation = package.documentation.clone().unwrap_or_default();
+        pack_info.homepage = package.homepage.clone().unwrap_or_default();
+        pack_info.repository = package.repository.clone().unwrap_or_default();
+        pack_info.readme = package.readme.clone().unwrap_or_default();
+        pack_info.keywords = package.keywords.clone();
+        pack_info.categories = package.categories.clone();
+        pack_info.edition = package.edition.clone();
+        pack_info.rust_version = package.rust_version.clone().unwrap_or_default();
+        pack_info.links = package.links.clone().unwrap_or_default();
+        pack_info.license_file = package.license_file.clone().unwrap_or_default();
+        package_context.features = package.features.clone();
+        let package_dir = package.manifest_path.parent().unwrap_or(Path::new(""));
+        package_context.targets = package
+            .targets
+            .iter()
+            .filter(|target| {
+                target
+                    .kind
+                    .iter()
+                    .any(|kind| kind == "bin" || LIB_KINDS.contains(&kind.as_str()))
+            })
+            .map(|target| TargetInfo {
+                name: target.name.clone(),
+                kind: target.kind.clone(),
+                src_path: target
+                    .src_path
+                    .strip_prefix(package_dir)
+                    .unwrap_or(&target.src_path)
+                    .to_string_lossy()
+                    .to_string(),
+            })
+            .collect();
+        let mut irresolve_depinfos = vec![];
+        for dep in package.dependencies.iter() {
+            let dep_info = dep.to_dep_info()?;
+            if dep_info.dump {
+                package_context.add_dep(dep_info);
+            } else {
+                irresolve_depinfos.push(Skipped::Dep(dep_info.name));
+            }
+        }
+        Ok(irresolve_depinfos)
+    }
+}
+
+impl MetaDependency {
+    fn to_dep_info(&self) -> Result<DepInfo, Error> {
+        let kind = match self.kind.as_deref() {
+            None => DepKind::Normal,
+            Some("dev") => DepKind::Dev,
+            Some("build") => DepKind::Build,
+            Some(kind) => {
+                return Err(Error::Cargo(format!(
+                    "dependency `{}`: unknown kind `{}`",
+                    self.name, kind
+                )))
+            }
+        };
+        let mut dep_info = DepInfo {
+            name: self.rename.clone().unwrap_or_else(|| self.name.clone()),
+            ver_req: self.req.clone(),
+            src_platform: self.target.clone().unwrap_or_default(),
+            kind,
+            features: self.features.clone(),
+            optional: self.optional,
+            default_features: self.uses_default_features,
+            package: match self.rename {
+                Some(_) => self.name.clone(),
+                None => "".to_string(),
+            },
+            ..Default::default()
+        };
+        match self.source.as_deref() {
+            Some(source) if source.starts_with("git+") => {
+                let (url, git_ref) = parse_git_source(source);
+                dep_info.src = SrcTypePath::Git(url);
+                dep_info.git_ref = git_ref;
+            }
+            Some(CRATES_IO_SOURCE) => {}
+            _ => {
+                if let Some(registry) = &self.registry {
+                    dep_info.src = SrcTypePath::Registry(registry.clone());
+                } else if self.path.is_some() && self.req == "*" {
+                    //a path dependency without `version`, see CrateToml
+                    if kind != DepKind::Dev {
+                        return Err(Error::Manifest(format!(
+                            "dependency `{}` has a `path` but no `version`, it can't be published",
+                            dep_info.name
+                        )));
+                    }
+                    dep_info.dump = false;
+                }
+            }
+        }
+        Ok(dep_info)
+    }
+}
+
+///`git+<url>[?branch=|tag=|rev=<ref>][#<commit>]`
+fn parse_git_source(source: &str) -> (String, GitRef) {
+    let source = source.trim_start_matches("git+");
+    let source = source.split('#').next().unwrap_or(source);
+    let (url, query) = source.split_once('?').unwrap_or((source, ""));
+    let git_ref = query
+        .split('&')
+        .find_map(|pair| match pair.split_once('=') {
+            Some(("branch", name)) => Some(GitRef::Branch(name.to_string())),
+            Some(("tag", name)) => Some(GitRef::Tag(name.to_string())),
+            Some(("rev", name)) => Some(GitRef::Rev(name.to_string())),
+            _ => None,
+        })
+        .unwrap_or_default();
+    (url.to_string(), git_ref)
+}
+
+#[test]
+fn test_from_metadata() {
+    let json = r#"{
+        "packages": [{
+            "name": "foo",
+            "version": "0.3.0",
+            "license": "MIT",
+            "authors": ["crate-spec"],
+            "description": "a foo",
+            "keywords": ["foo"],
+            "edition": "2021",
+            "rust_version": "1.70",
+            "features": {"default": ["std"], "std": ["serde?/std"], "serde": ["dep:serde"]},
+            "manifest_path": "/ws/foo/Cargo.toml",
+            "targets": [
+                {"name": "foo", "kind": ["lib"], "src_path": "/ws/foo/src/lib.rs"},
+                {"name": "foo-cli", "kind": ["bin"], "src_path": "/ws/foo/src/bin/cli.rs"},
+                {"name": "build-script-build", "kind": ["custom-build"], "src_path": "/ws/foo/build.rs"},
+                {"name": "it", "kind": ["test"], "src_path": "/ws/foo/tests/it.rs"}
+            ],
+            "dependencies": [
+                {"name": "serde", "source": "registry+https://github.com/rust-lang/crates.io-index",
+                 "req": "^1.0", "kind": null, "rename": null, "optional": true,
+                 "uses_default_features": false, "features": ["derive"], "target": null,
+                 "registry": null},
+                {"name": "rand-core-fork", "source": "git+https://example.com/rand?tag=v1.0",
+                 "req": "*", "kind": "build", "rename": "rand_core", "optional": false,
+                 "uses_default_features": true, "features": [], "target": "cfg(unix)",
+                 "registry": null},
+                {"name": "helper", "source": null, "req": "*", "kind": "dev", "rename": null,
+                 "optional": false, "uses_default_features": true, "features": [],
+                 "target": null, "registry": null, "path": "/ws/helper"}
+            ]
+        }],
+        "target_directory": "/ws/target",
+        "workspace_root": "/ws"
+    }"#;
+    let metadata = CrateMetadata::from_json(json, Path::new("/ws/foo/Cargo.toml")).unwrap();
+    assert_eq!(metadata.target_directory(), Path::new("/ws/target"));
+    let mut pack_context = PackageContext::new();
+    let skipped = metadata
+        .write_info_to_package_context(&mut pack_context)
+        .unwrap();
+    assert_eq!(skipped, [Skipped::Dep("helper".to_string())]);
+    assert_eq!(pack_context.pack_info.version, "0.3.0");
+    assert_eq!(pack_context.pack_info.description, "a foo");
+    assert_eq!(pack_context.pack_info.keywords, ["foo"]);
+    assert_eq!(pack_context.pack_info.rust_version, "1.70");
+    assert_eq!(pack_context.features["std"], ["serde?/std"]);
+    assert_eq!(
+        pack_context.targets,
+        [
+            TargetInfo {
+                name: "foo".to_string(),
+                kind: vec!["lib".to_string()],
+                src_path: "src/lib.rs".to_string(),
+            },
+            TargetInfo {
+                name: "foo-cli".to_string(),
+                kind: vec!["bin".to_string()],
+                src_path: "src/bin/cli.rs".to_string(),
+            },
+        ]
+    );
+
+    let serde = &pack_context.dep_infos[0];
+    assert_eq!(serde.src, SrcTypePath::CratesIo);
+    assert!(serde.optional && !serde.default_features);
+    let rand = &pack_context.dep_infos[1];
+    assert_eq!(
+        (rand.name.as_str(), rand.package.as_str()),
+        ("rand_core", "rand-core-fork")
+    );
+    assert_eq!(rand.kind, DepKind::Build);
+    assert_eq!(rand.src_platform, "cfg(unix)");
+    assert_eq!(
+        rand.src,
+        SrcTypePath::Git("https://example.com/rand".to_string())
+    );
+    assert_eq!(rand.git_ref, GitRef::Tag("v1.0".to_string()));
+
+    assert!(CrateMetadata::from_json(json, Path::new("/ws/bar/Cargo.toml")).is_err());
+}
+
+#[test]
+fn test_workspace_members() {
+    let json = r#"{
+        "packages": [
+            {"id": "foo 0.3.0", "name": "foo", "version": "0.3.0", "license": null,
+             "manifest_path": "/ws/foo/Cargo.toml", "dependencies": [], "publish": null},
+            {"id": "internal 0.1.0", "name": "internal", "version": "0.1.0", "license": null,
+             "manifest_path": "/ws/internal/Cargo.toml", "dependencies": [], "publish": []}
+        ],
+        "workspace_members": ["foo 0.3.0", "internal 0.1.0"],
+        "target_directory": "/ws/target"
+    }"#;
+    let members = workspace_members(json).unwrap();
+    assert_eq!(members.len(), 2);
+    assert_eq!(members[0].manifest_dir, Path::new("/ws/foo"));
+    assert!(members[0].publish);
+    assert!(!members[1].publish);
+}
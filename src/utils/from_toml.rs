@@ -1,135 +1,972 @@
-use crate::utils::context::{DepInfo, PackageContext, SrcTypePath};
-use std::collections::HashSet;
+use crate::utils::context::{DepInfo, DepKind, GitRef, PackageContext, Skipped, SrcTypePath};
+use crate::utils::error::Error;
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
-use toml::Table;
+use toml::{Table, Value};
 
 #[derive(Default)]
 pub struct CrateToml {
     t: Table,
+    ///directory of the workspace root manifest, if the crate is in a workspace
+    workspace_root: Option<PathBuf>,
 }
 
 impl CrateToml {
-    pub fn from_file(path: String) -> CrateToml {
-        let f = fs::read(Path::new(path.as_str())).unwrap();
-        CrateToml::from_vec(f)
+    ///read the manifest and resolve what it inherits from its workspace
+    pub fn from_file(path: String) -> Result<CrateToml, Error> {
+        let f = fs::read(Path::new(path.as_str()))?;
+        let mut toml = CrateToml {
+            t: parse_table(f)?,
+            workspace_root: None,
+        };
+        let manifest_dir = Path::new(path.as_str())
+            .parent()
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+        if toml.t.contains_key("workspace") {
+            toml.workspace_root = Some(manifest_dir);
+            toml.resolve_own_workspace()?;
+        } else if let Some((root, workspace)) = toml.find_workspace(&manifest_dir)? {
+            toml.workspace_root = Some(root);
+            toml.resolve_workspace(&workspace)?;
+        } else {
+            toml.resolve_own_workspace()?;
+        }
+        Ok(toml)
+    }
+
+    pub fn from_vec(st_vec: Vec<u8>) -> Result<CrateToml, Error> {
+        let st = String::from_utf8(st_vec)
+            .map_err(|_| Error::Manifest("Cargo.toml is not valid utf-8".to_string()))?;
+        CrateToml::from_string(st.as_str())
+    }
+
+    ///parse the manifest, only a `[workspace]` of its own is used for inheritance
+    pub fn from_string(st: &str) -> Result<CrateToml, Error> {
+        let mut toml = CrateToml {
+            t: Table::from_str(st)?,
+            workspace_root: None,
+        };
+        toml.resolve_own_workspace()?;
+        Ok(toml)
+    }
+
+    fn resolve_own_workspace(&mut self) -> Result<(), Error> {
+        let workspace = match self.t.get("workspace") {
+            Some(workspace) => as_table(workspace, "workspace")?.clone(),
+            None => Table::new(),
+        };
+        self.resolve_workspace(&workspace)
+    }
+
+    pub fn workspace_root(&self) -> Option<&Path> {
+        self.workspace_root.as_deref()
+    }
+
+    ///`[workspace]` of the root manifest and its directory, found like cargo does:
+    ///`package.workspace` if given, else the closest parent directory whose Cargo.toml
+    ///has one that doesn't exclude the package. errors if the package isn't a member of it
+    fn find_workspace(&self, manifest_dir: &Path) -> Result<Option<(PathBuf, Table)>, Error> {
+        let explicit = match self.t.get("package").and_then(|p| p.get("workspace")) {
+            Some(path) => Some(fs::canonicalize(
+                manifest_dir.join(as_str(path, "package.workspace")?),
+            )?),
+            None => None,
+        };
+        let manifest_dir = fs::canonicalize(manifest_dir)?;
+        let candidates: Vec<PathBuf> = match &explicit {
+            Some(root) => vec![root.clone()],
+            None => manifest_dir
+                .ancestors()
+                .skip(1)
+                .map(Path::to_path_buf)
+                .collect(),
+        };
+        for dir in candidates {
+            let root_path = dir.join("Cargo.toml");
+            if !root_path.is_file() {
+                continue;
+            }
+            let root = parse_table(fs::read(&root_path)?)?;
+            let Some(workspace) = root.get("workspace") else {
+                continue;
+            };
+            let mut workspace = as_table(workspace, "workspace")?.clone();
+            if explicit.is_none() && is_excluded(&workspace, &dir, &manifest_dir)? {
+                continue;
+            }
+            if !is_member(&root, &workspace, &dir, &manifest_dir)? {
+                return Err(Error::Manifest(format!(
+                    "{} believes it's in the workspace at {}, but isn't one of its members",
+                    manifest_dir.display(),
+                    dir.display()
+                )));
+            }
+            rebase_workspace(&mut workspace, &dir, &manifest_dir);
+            return Ok(Some((dir, workspace)));
+        }
+        Ok(None)
+    }
+
+    ///replace `field.workspace = true` and `dep = { workspace = true }` with the values
+    ///of `[workspace.package]` and `[workspace.dependencies]`
+    fn resolve_workspace(&mut self, workspace: &Table) -> Result<(), Error> {
+        let empty = Table::new();
+        let ws_package = match workspace.get("package") {
+            Some(package) => as_table(package, "workspace.package")?,
+            None => &empty,
+        };
+        let ws_deps = match workspace.get("dependencies") {
+            Some(deps) => as_table(deps, "workspace.dependencies")?,
+            None => &empty,
+        };
+
+        if let Some(Value::Table(package)) = self.t.get_mut("package") {
+            for (key, val) in package.iter_mut() {
+                if !is_inherited(val) {
+                    continue;
+                }
+                *val = ws_package.get(key).cloned().ok_or_else(|| {
+                    Error::Manifest(format!(
+                        "`package.{}` is inherited but `workspace.package.{}` is missing",
+                        key, key
+                    ))
+                })?;
+            }
+        }
+
+        resolve_workspace_deps(&mut self.t, ws_deps)?;
+        if let Some(Value::Table(targets)) = self.t.get_mut("target") {
+            for (_, target) in targets.iter_mut() {
+                if let Value::Table(target) = target {
+                    resolve_workspace_deps(target, ws_deps)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+///a workspace skips a package under one of its `exclude` paths,
+///unless one of its `members` paths holds it too
+fn is_excluded(workspace: &Table, root: &Path, member: &Path) -> Result<bool, Error> {
+    let under = |key: &str| -> Result<bool, Error> {
+        Ok(workspace_strs(workspace, key)?
+            .iter()
+            .any(|path| member.starts_with(root.join(path))))
+    };
+    Ok(under("exclude")? && !under("members")?)
+}
+
+///whether the package in `member` belongs to the workspace at `root`: it's the root
+///package, matches `members`, or is a path dependency of a member inside the root
+fn is_member(
+    root_manifest: &Table,
+    workspace: &Table,
+    root: &Path,
+    member: &Path,
+) -> Result<bool, Error> {
+    let mut pending = vec![];
+    if root_manifest.contains_key("package") {
+        pending.push(root.to_path_buf());
+    }
+    for pattern in workspace_strs(workspace, "members")? {
+        pending.extend(expand_glob(root, pattern.as_str()));
+    }
+    let mut seen = HashSet::new();
+    while let Some(dir) = pending.pop() {
+        if dir == member {
+            return Ok(true);
+        }
+        let manifest_path = dir.join("Cargo.toml");
+        if !seen.insert(dir.clone()) || !manifest_path.is_file() {
+            continue;
+        }
+        let manifest = parse_table(fs::read(manifest_path)?)?;
+        for dep_dir in path_deps(&manifest, &dir, workspace, root) {
+            if dep_dir.starts_with(root) && !is_excluded(workspace, root, &dep_dir)? {
+                pending.push(dep_dir);
+            }
+        }
+    }
+    Ok(false)
+}
+
+///directories of the path dependencies of `manifest`, which is in `dir`;
+///inherited ones take their path from `[workspace.dependencies]`
+fn path_deps(manifest: &Table, dir: &Path, workspace: &Table, root: &Path) -> Vec<PathBuf> {
+    let ws_deps = workspace.get("dependencies");
+    let mut parents = vec![manifest];
+    if let Some(Value::Table(targets)) = manifest.get("target") {
+        parents.extend(targets.values().filter_map(Value::as_table));
+    }
+    let mut dirs = vec![];
+    for parent in parents {
+        for kind in [DepKind::Normal, DepKind::Dev, DepKind::Build] {
+            for table in [kind.table().to_string(), kind.table().replace('-', "_")] {
+                let Some(Value::Table(deps)) = parent.get(table.as_str()) else {
+                    continue;
+                };
+                for (name, dep) in deps.iter() {
+                    let (base, dep) = match is_inherited(dep) {
+                        true => (root, ws_deps.and_then(|deps| deps.get(name))),
+                        false => (dir, Some(dep)),
+                    };
+                    if let Some(path) = dep.and_then(|dep| dep.get("path")).and_then(Value::as_str)
+                    {
+                        dirs.push(normalize(&base.join(path)));
+                    }
+                }
+            }
+        }
+    }
+    dirs
+}
+
+///the directories `pattern`, relative to `root`, matches; components may hold
+///`*` and `?` wildcards like cargo's `members` globs
+fn expand_glob(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![root.to_path_buf()];
+    for component in Path::new(pattern).components() {
+        let component = component.as_os_str().to_string_lossy();
+        if !component.contains(['*', '?']) {
+            dirs = dirs
+                .iter()
+                .map(|dir| dir.join(component.as_ref()))
+                .collect();
+            continue;
+        }
+        let mut matched = vec![];
+        for dir in dirs.iter() {
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                if wildcard_match(component.as_bytes(), name.to_string_lossy().as_bytes()) {
+                    matched.push(entry.path());
+                }
+            }
+        }
+        dirs = matched;
+    }
+    dirs.iter().map(|dir| normalize(dir)).collect()
+}
+
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            wildcard_match(rest, name) || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name_rest))) => wildcard_match(rest, name_rest),
+        (Some((p, rest)), Some((n, name_rest))) => p == n && wildcard_match(rest, name_rest),
+        _ => false,
     }
+}
 
-    pub fn from_vec(st_vec: Vec<u8>) -> CrateToml {
-        CrateToml::from_string(String::from_utf8(st_vec).unwrap().as_str())
+///`path` with `.` and `..` resolved without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
     }
+    normalized
+}
 
-    pub fn from_string(st: &str) -> CrateToml {
-        CrateToml {
-            t: Table::from_str(st).unwrap(),
+///paths in `[workspace.package]` and `[workspace.dependencies]` are relative to the root,
+///rebase them onto the member's directory as cargo does when they are inherited
+fn rebase_workspace(workspace: &mut Table, root: &Path, member: &Path) {
+    if let Some(Value::Table(package)) = workspace.get_mut("package") {
+        for key in ["readme", "license-file"] {
+            if let Some(Value::String(path)) = package.get_mut(key) {
+                *path = rebase_path(path, root, member);
+            }
+        }
+    }
+    if let Some(Value::Table(deps)) = workspace.get_mut("dependencies") {
+        for (_, dep) in deps.iter_mut() {
+            if let Some(Value::String(path)) = dep.get_mut("path") {
+                *path = rebase_path(path, root, member);
+            }
         }
     }
 }
 
+///`path`, relative to `root`, made relative to `member`
+fn rebase_path(path: &str, root: &Path, member: &Path) -> String {
+    if Path::new(path).is_absolute() {
+        return path.to_string();
+    }
+    let target = normalize(&root.join(path));
+    let common = target
+        .components()
+        .zip(member.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut rebased: Vec<String> = member
+        .components()
+        .skip(common)
+        .map(|_| "..".to_string())
+        .collect();
+    rebased.extend(
+        target
+            .components()
+            .skip(common)
+            .map(|component| component.as_os_str().to_string_lossy().to_string()),
+    );
+    match rebased.is_empty() {
+        true => ".".to_string(),
+        false => rebased.join("/"),
+    }
+}
+
+///`{ workspace = true }`
+fn is_inherited(val: &Value) -> bool {
+    val.get("workspace").and_then(Value::as_bool) == Some(true)
+}
+
+///resolve the inherited dependencies of every dependency table of `parent`
+fn resolve_workspace_deps(parent: &mut Table, ws_deps: &Table) -> Result<(), Error> {
+    for kind in [DepKind::Normal, DepKind::Dev, DepKind::Build] {
+        for table in [kind.table().to_string(), kind.table().replace('-', "_")] {
+            if let Some(Value::Table(deps)) = parent.get_mut(table.as_str()) {
+                for (name, dep) in deps.iter_mut() {
+                    if is_inherited(dep) {
+                        *dep = inherit_dep(name, dep, ws_deps)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+///the workspace dependency `name`, with the member's `features` added and its `optional`
+fn inherit_dep(name: &str, member: &Value, ws_deps: &Table) -> Result<Value, Error> {
+    let mut dep = match ws_deps.get(name) {
+        Some(Value::String(version)) => {
+            Table::from_iter([("version".to_string(), Value::String(version.clone()))])
+        }
+        Some(Value::Table(dep)) => dep.clone(),
+        Some(_) => {
+            return Err(Error::Manifest(format!(
+                "`workspace.dependencies.{}` should be a string or a table",
+                name
+            )))
+        }
+        None => {
+            return Err(Error::Manifest(format!(
+                "dependency `{}` is inherited but `workspace.dependencies.{}` is missing",
+                name, name
+            )))
+        }
+    };
+    for (key, val) in as_table(member, name)?.iter() {
+        match key.as_str() {
+            "workspace" => {}
+            "features" => {
+                let features = dep
+                    .entry("features")
+                    .or_insert_with(|| Value::Array(vec![]))
+                    .as_array_mut()
+                    .ok_or_else(|| Error::Manifest("`features` should be an array".to_string()))?;
+                for feature in val
+                    .as_array()
+                    .ok_or_else(|| Error::Manifest("`features` should be an array".to_string()))?
+                {
+                    if !features.contains(feature) {
+                        features.push(feature.clone());
+                    }
+                }
+            }
+            "optional" => {
+                dep.insert(key.clone(), val.clone());
+            }
+            _ => {
+                return Err(Error::Manifest(format!(
+                    "dependency `{}`: `{}` can't be combined with `workspace = true`",
+                    name, key
+                )))
+            }
+        }
+    }
+    Ok(Value::Table(dep))
+}
+
+fn parse_table(st_vec: Vec<u8>) -> Result<Table, Error> {
+    let st = String::from_utf8(st_vec)
+        .map_err(|_| Error::Manifest("Cargo.toml is not valid utf-8".to_string()))?;
+    Ok(Table::from_str(st.as_str())?)
+}
+
+fn as_str<'a>(val: &'a Value, key: &str) -> Result<&'a str, Error> {
+    val.as_str()
+        .ok_or_else(|| Error::Manifest(format!("`{}` should be a string", key)))
+}
+
+fn as_bool(val: &Value, key: &str) -> Result<bool, Error> {
+    val.as_bool()
+        .ok_or_else(|| Error::Manifest(format!("`{}` should be a boolean", key)))
+}
+
+fn as_table<'a>(val: &'a Value, key: &str) -> Result<&'a Table, Error> {
+    val.as_table()
+        .ok_or_else(|| Error::Manifest(format!("`{}` should be a table", key)))
+}
+
+///`package.<key>`, empty if unset
+fn package_str(package: &Table, key: &str) -> Result<String, Error> {
+    match package.get(key) {
+        Some(val) => Ok(as_str(val, format!("package.{}", key).as_str())?.to_string()),
+        None => Ok("".to_string()),
+    }
+}
+
+///`package.<key>` as an array of strings, empty if unset
+fn package_strs(package: &Table, key: &str) -> Result<Vec<String>, Error> {
+    let full_key = format!("package.{}", key);
+    match package.get(key) {
+        Some(val) => val
+            .as_array()
+            .ok_or_else(|| Error::Manifest(format!("`{}` should be an array", full_key)))?
+            .iter()
+            .map(|x| as_str(x, full_key.as_str()).map(|x| x.to_string()))
+            .collect(),
+        None => Ok(vec![]),
+    }
+}
+
+///`workspace.<key>` as an array of strings, empty if unset
+fn workspace_strs(workspace: &Table, key: &str) -> Result<Vec<String>, Error> {
+    let full_key = format!("workspace.{}", key);
+    match workspace.get(key) {
+        Some(val) => val
+            .as_array()
+            .ok_or_else(|| Error::Manifest(format!("`{}` should be an array", full_key)))?
+            .iter()
+            .map(|x| as_str(x, full_key.as_str()).map(|x| x.to_string()))
+            .collect(),
+        None => Ok(vec![]),
+    }
+}
+
 impl CrateToml {
     fn write_package_info_to_package_context(
         &self,
         package_context: &mut PackageContext,
         package: &Table,
-    ) {
-        let name = package["name"].as_str().unwrap().to_string();
-        let version = package["version"].as_str().unwrap().to_string();
-        let mut license = "".to_string();
-        let mut authors = Vec::<String>::new();
-        if package.contains_key("license") {
-            license = package["license"].as_str().unwrap().to_string();
-        }
-        if package.contains_key("authors") {
-            authors = package["authors"]
-                .as_array()
-                .unwrap()
-                .iter()
-                .map(|x| x.as_str().unwrap().to_string())
-                .collect();
-        }
+    ) -> Result<(), Error> {
+        let name = package
+            .get("name")
+            .ok_or_else(|| Error::Manifest("`package.name` is missing".to_string()))?;
+        let name = as_str(name, "package.name")?.to_string();
+        let version = package
+            .get("version")
+            .ok_or_else(|| Error::Manifest("`package.version` is missing".to_string()))?;
+        let version = as_str(version, "package.version")?.to_string();
+        let license = package_str(package, "license")?;
+        let authors = package_strs(package, "authors")?;
         package_context.set_package_info(name, version, license, authors);
+
+        let pack_info = &mut package_context.pack_info;
+        pack_info.description = package_str(package, "description")?;
+        pack_info.documentation = package_str(package, "documentation")?;
+        pack_info.homepage = package_str(package, "homepage")?;
+        pack_info.repository = package_str(package, "repository")?;
+        //`readme = false` turns the readme off, `true` is README.md
+        pack_info.readme = match package.get("readme") {
+            Some(Value::Boolean(true)) => "README.md".to_string(),
+            Some(Value::Boolean(false)) => "".to_string(),
+            _ => package_str(package, "readme")?,
+        };
+        pack_info.keywords = package_strs(package, "keywords")?;
+        pack_info.categories = package_strs(package, "categories")?;
+        pack_info.edition = package_str(package, "edition")?;
+        pack_info.rust_version = package_str(package, "rust-version")?;
+        pack_info.links = package_str(package, "links")?;
+        pack_info.license_file = package_str(package, "license-file")?;
+        Ok(())
     }
 
+    ///write the dependencies of one table, returns the dependencies skipped because
+    ///cargo strips them when packaging (path-only dev-dependencies) and the
+    ///dependency keys the dependency table can't represent
     fn write_dep_info_to_package_context(
         &self,
         package_context: &mut PackageContext,
         deps: &Table,
         platform: String,
-    ) -> Vec<String> {
+        kind: DepKind,
+    ) -> Result<Vec<Skipped>, Error> {
         let mut irresolve_depinfos = vec![];
         for dep in deps.iter() {
             let mut dep_info = DepInfo {
                 src_platform: platform.to_string(),
                 name: dep.0.to_string(),
+                kind,
                 ..Default::default()
             };
             let val = dep.1;
             if val.is_str() {
-                dep_info.ver_req = val.as_str().unwrap().to_string();
+                dep_info.ver_req = as_str(val, dep.0)?.to_string();
             } else {
-                let attri_map = val.as_table().unwrap();
+                let attri_map = as_table(val, dep.0)?;
                 let allow_keys = HashSet::from([
-                    "version".to_string(),
-                    "git".to_string(),
-                    "registry".to_string(),
+                    "version",
+                    "git",
+                    "branch",
+                    "tag",
+                    "rev",
+                    "registry",
+                    "registry-index",
+                    "path",
+                    "package",
+                    "features",
+                    "optional",
+                    "default-features",
+                    "default_features",
                 ]);
-                for attri in attri_map.keys() {
-                    if !allow_keys.contains(attri) {
-                        dep_info.dump = false;
-                    }
-                }
+                //e.g. `public`, `artifact`, `lib` or `target`: the dependency is kept without them
+                irresolve_depinfos.extend(
+                    attri_map
+                        .keys()
+                        .filter(|attri| !allow_keys.contains(attri.as_str()))
+                        .map(|attri| Skipped::DepKey(dep.0.to_string(), attri.to_string())),
+                );
                 if attri_map.contains_key("version") {
-                    dep_info.ver_req = attri_map["version"].as_str().unwrap().to_string();
+                    dep_info.ver_req = as_str(&attri_map["version"], "version")?.to_string();
+                } else if attri_map.contains_key("path") {
+                    //cargo drops path-only dev-dependencies from the published manifest
+                    //and refuses to package the other kinds
+                    if kind != DepKind::Dev {
+                        return Err(Error::Manifest(format!(
+                            "dependency `{}` has a `path` but no `version`, it can't be published",
+                            dep.0
+                        )));
+                    }
+                    dep_info.dump = false;
                 }
                 if attri_map.contains_key("git") {
-                    dep_info.src = SrcTypePath::Git(attri_map["git"].as_str().unwrap().to_string());
+                    dep_info.src = SrcTypePath::Git(as_str(&attri_map["git"], "git")?.to_string());
+                    for (key, git_ref) in [
+                        ("branch", GitRef::Branch as fn(String) -> GitRef),
+                        ("tag", GitRef::Tag),
+                        ("rev", GitRef::Rev),
+                    ] {
+                        if let Some(val) = attri_map.get(key) {
+                            if dep_info.git_ref != GitRef::DefaultBranch {
+                                return Err(Error::Manifest(format!(
+                                    "dependency `{}`: only one of `branch`, `tag` or `rev` is allowed",
+                                    dep.0
+                                )));
+                            }
+                            dep_info.git_ref = git_ref(as_str(val, key)?.to_string());
+                        }
+                    }
+                } else if let Some(key) = ["branch", "tag", "rev"]
+                    .into_iter()
+                    .find(|key| attri_map.contains_key(*key))
+                {
+                    return Err(Error::Manifest(format!(
+                        "dependency `{}`: `{}` is only allowed with `git`",
+                        dep.0, key
+                    )));
                 }
                 if attri_map.contains_key("registry") {
-                    dep_info.src =
-                        SrcTypePath::Registry(attri_map["registry"].as_str().unwrap().to_string());
+                    dep_info.src = SrcTypePath::Registry(
+                        as_str(&attri_map["registry"], "registry")?.to_string(),
+                    );
+                }
+                //published manifests name the registry by its index url
+                if attri_map.contains_key("registry-index") {
+                    dep_info.src = SrcTypePath::Registry(
+                        as_str(&attri_map["registry-index"], "registry-index")?.to_string(),
+                    );
+                }
+                if attri_map.contains_key("package") {
+                    dep_info.package = as_str(&attri_map["package"], "package")?.to_string();
+                }
+                if attri_map.contains_key("features") {
+                    dep_info.features = attri_map["features"]
+                        .as_array()
+                        .ok_or_else(|| {
+                            Error::Manifest("`features` should be an array".to_string())
+                        })?
+                        .iter()
+                        .map(|x| as_str(x, "features").map(|x| x.to_string()))
+                        .collect::<Result<_, _>>()?;
+                }
+                if attri_map.contains_key("optional") {
+                    dep_info.optional = as_bool(&attri_map["optional"], "optional")?;
+                }
+                for key in ["default-features", "default_features"] {
+                    if attri_map.contains_key(key) {
+                        dep_info.default_features = as_bool(&attri_map[key], key)?;
+                    }
                 }
             }
             if dep_info.dump {
-                package_context.add_dep_info(
-                    dep_info.name,
-                    dep_info.ver_req,
-                    dep_info.src,
-                    dep_info.src_platform,
-                );
+                package_context.add_dep(dep_info);
             } else {
-                irresolve_depinfos.push(dep_info.name);
+                irresolve_depinfos.push(Skipped::Dep(dep_info.name));
             }
         }
-        irresolve_depinfos
+        Ok(irresolve_depinfos)
     }
 
     pub fn write_info_to_package_context(
         &self,
         package_context: &mut PackageContext,
-    ) -> Vec<String> {
-        assert!(self.t.contains_key("package"));
-        self.write_package_info_to_package_context(
-            package_context,
-            self.t.get("package").unwrap().as_table().unwrap(),
-        );
-        //FIXME current platform is not considered, we only consider [dependencies], see https://course.rs/cargo/reference/specify-deps.html#build-dependencies
-        let excluded_crate = self.write_dep_info_to_package_context(
-            package_context,
-            self.t.get("dependencies").unwrap().as_table().unwrap(),
-            "".to_string(),
-        );
-        excluded_crate
+    ) -> Result<Vec<Skipped>, Error> {
+        let package = self
+            .t
+            .get("package")
+            .ok_or_else(|| Error::Manifest("`[package]` is missing".to_string()))?;
+        self.write_package_info_to_package_context(package_context, as_table(package, "package")?)?;
+        let mut excluded_crate =
+            self.write_deps_tables_to_package_context(package_context, &self.t, "", "")?;
+        //[target.'cfg(...)'.dependencies] and [target.<triple>.dependencies]
+        if let Some(targets) = self.t.get("target") {
+            for (platform, target) in as_table(targets, "target")?.iter() {
+                let key = format!("target.{}.", platform);
+                excluded_crate.extend(self.write_deps_tables_to_package_context(
+                    package_context,
+                    as_table(target, key.trim_end_matches('.'))?,
+                    platform,
+                    key.as_str(),
+                )?);
+            }
+        }
+        self.write_features_to_package_context(package_context)?;
+        Ok(excluded_crate)
     }
+
+    ///write `[features]` plus the implicit feature cargo adds for every optional
+    ///dependency that no feature refers to as `dep:<name>`
+    fn write_features_to_package_context(
+        &self,
+        package_context: &mut PackageContext,
+    ) -> Result<(), Error> {
+        let mut features = BTreeMap::new();
+        if let Some(table) = self.t.get("features") {
+            for (name, items) in as_table(table, "features")?.iter() {
+                let key = format!("features.{}", name);
+                let items = items
+                    .as_array()
+                    .ok_or_else(|| Error::Manifest(format!("`{}` should be an array", key)))?
+                    .iter()
+                    .map(|item| as_str(item, key.as_str()).map(|item| item.to_string()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                features.insert(name.clone(), items);
+            }
+        }
+        let explicit: HashSet<String> = features
+            .values()
+            .flatten()
+            .filter_map(|item| item.strip_prefix("dep:"))
+            .map(str::to_string)
+            .collect();
+        for dep in package_context.dep_infos.iter() {
+            if dep.optional && !explicit.contains(&dep.name) && !features.contains_key(&dep.name) {
+                features.insert(dep.name.clone(), vec![format!("dep:{}", dep.name)]);
+            }
+        }
+        package_context.features = features;
+        Ok(())
+    }
+
+    ///write `[dependencies]`, `[dev-dependencies]` and `[build-dependencies]` of `parent`
+    fn write_deps_tables_to_package_context(
+        &self,
+        package_context: &mut PackageContext,
+        parent: &Table,
+        platform: &str,
+        key_prefix: &str,
+    ) -> Result<Vec<Skipped>, Error> {
+        let mut excluded_crate = vec![];
+        for kind in [DepKind::Normal, DepKind::Dev, DepKind::Build] {
+            //cargo still accepts the old underscore spelling
+            let deps = parent
+                .get(kind.table())
+                .or_else(|| parent.get(kind.table().replace('-', "_").as_str()));
+            if let Some(deps) = deps {
+                excluded_crate.extend(self.write_dep_info_to_package_context(
+                    package_context,
+                    as_table(deps, format!("{}{}", key_prefix, kind.table()).as_str())?,
+                    platform.to_string(),
+                    kind,
+                )?);
+            }
+        }
+        Ok(excluded_crate)
+    }
+}
+
+#[test]
+fn test_toml_dep_kinds() {
+    let toml = CrateToml::from_string(
+        r#"
+[package]
+name = "foo"
+version = "0.1.0"
+
+[dependencies]
+log = "0.4"
+
+[dev-dependencies]
+tempfile = "3"
+
+[build-dependencies]
+cc = "1"
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+
+[target.x86_64-pc-windows-gnu.build-dependencies]
+winres = "0.1"
+"#,
+    )
+    .unwrap();
+    let mut pack_context = PackageContext::new();
+    toml.write_info_to_package_context(&mut pack_context)
+        .unwrap();
+    let deps: Vec<_> = pack_context
+        .dep_infos
+        .iter()
+        .map(|dep| (dep.name.as_str(), dep.kind, dep.src_platform.as_str()))
+        .collect();
+    assert_eq!(
+        deps,
+        [
+            ("log", DepKind::Normal, ""),
+            ("tempfile", DepKind::Dev, ""),
+            ("cc", DepKind::Build, ""),
+            ("libc", DepKind::Normal, "cfg(unix)"),
+            ("winres", DepKind::Build, "x86_64-pc-windows-gnu"),
+        ]
+    );
+}
+
+#[test]
+fn test_toml_dep_attributes() {
+    let toml = CrateToml::from_string(
+        r#"
+[package]
+name = "foo"
+version = "0.1.0"
+
+[dependencies]
+serde = { version = "1", features = ["derive"], optional = true, default-features = false }
+rand_core = { version = "0.6", package = "rand-core-fork" }
+bar = { git = "https://example.com/bar", tag = "v1.0" }
+local = { path = "../local", version = "0.2" }
+
+[dev-dependencies]
+helper = { path = "../helper" }
+"#,
+    )
+    .unwrap();
+    let mut pack_context = PackageContext::new();
+    let skipped = toml
+        .write_info_to_package_context(&mut pack_context)
+        .unwrap();
+    assert_eq!(skipped, [Skipped::Dep("helper".to_string())]);
+    let dep = |name: &str| {
+        pack_context
+            .dep_infos
+            .iter()
+            .find(|dep| dep.name == name)
+            .unwrap()
+            .clone()
+    };
+    let serde = dep("serde");
+    assert_eq!(serde.features, ["derive"]);
+    assert!(serde.optional);
+    assert!(!serde.default_features);
+    assert_eq!(dep("rand_core").package, "rand-core-fork");
+    assert_eq!(dep("bar").git_ref, GitRef::Tag("v1.0".to_string()));
+    assert_eq!(dep("local").ver_req, "0.2");
+
+    //keys the dependency table has no field for are skipped, the dependency is kept
+    let toml = CrateToml::from_string(
+        "[package]\nname = \"foo\"\nversion = \"0.1.0\"\n\
+         [dependencies]\nfoo = { version = \"1\", public = true }",
+    )
+    .unwrap();
+    let mut pack_context = PackageContext::new();
+    let skipped = toml
+        .write_info_to_package_context(&mut pack_context)
+        .unwrap();
+    assert_eq!(
+        skipped,
+        [Skipped::DepKey("foo".to_string(), "public".to_string())]
+    );
+    assert_eq!(pack_context.dep_infos.len(), 1);
+    assert_eq!(pack_context.dep_infos[0].ver_req, "1");
+
+    for manifest in [
+        "[dependencies]\nfoo = { path = \"../foo\" }",
+        "[dependencies]\nfoo = { git = \"https://example.com/foo\", tag = \"a\", rev = \"b\" }",
+    ] {
+        let toml = CrateToml::from_string(
+            format!(
+                "[package]\nname = \"foo\"\nversion = \"0.1.0\"\n{}",
+                manifest
+            )
+            .as_str(),
+        )
+        .unwrap();
+        assert!(matches!(
+            toml.write_info_to_package_context(&mut PackageContext::new()),
+            Err(Error::Manifest(_))
+        ));
+    }
+}
+
+#[test]
+fn test_toml_package_fields() {
+    let toml = CrateToml::from_string(
+        r#"
+[package]
+name = "foo"
+version = "0.1.0"
+description = "a foo"
+repository = "https://example.com/foo"
+readme = true
+keywords = ["foo", "bar"]
+categories = ["encoding"]
+rust-version = "1.70"
+links = "z"
+license-file = "LICENSE.txt"
+"#,
+    )
+    .unwrap();
+    let mut pack_context = PackageContext::new();
+    toml.write_info_to_package_context(&mut pack_context)
+        .unwrap();
+    let pack_info = &pack_context.pack_info;
+    assert_eq!(pack_info.description, "a foo");
+    assert_eq!(pack_info.repository, "https://example.com/foo");
+    assert_eq!(pack_info.readme, "README.md");
+    assert_eq!(pack_info.keywords, ["foo", "bar"]);
+    assert_eq!(pack_info.categories, ["encoding"]);
+    assert_eq!(pack_info.rust_version, "1.70");
+    assert_eq!(pack_info.links, "z");
+    assert_eq!(pack_info.license_file, "LICENSE.txt");
+    assert!(pack_info.homepage.is_empty());
+
+    let toml = CrateToml::from_string(
+        "[package]\nname = \"foo\"\nversion = \"0.1.0\"\nkeywords = \"foo\"",
+    )
+    .unwrap();
+    assert!(matches!(
+        toml.write_info_to_package_context(&mut PackageContext::new()),
+        Err(Error::Manifest(_))
+    ));
+}
+
+#[test]
+fn test_toml_features() {
+    let toml = CrateToml::from_string(
+        r#"
+[package]
+name = "foo"
+version = "0.1.0"
+
+[dependencies]
+serde = { version = "1", optional = true }
+rand = { version = "0.8", optional = true }
+log = { version = "0.4", optional = true }
+
+[features]
+default = ["std"]
+std = ["serde?/std", "rand/std"]
+json = ["dep:serde"]
+"#,
+    )
+    .unwrap();
+    let mut pack_context = PackageContext::new();
+    toml.write_info_to_package_context(&mut pack_context)
+        .unwrap();
+    let features = &pack_context.features;
+    assert_eq!(features["default"], ["std"]);
+    assert_eq!(features["std"], ["serde?/std", "rand/std"]);
+    assert_eq!(features["json"], ["dep:serde"]);
+    //serde is hidden behind `dep:`, rand and log get implicit features
+    assert!(!features.contains_key("serde"));
+    assert_eq!(features["rand"], ["dep:rand"]);
+    assert_eq!(features["log"], ["dep:log"]);
+}
+
+#[test]
+fn test_toml_workspace() {
+    let toml = CrateToml::from_file("test/workspace/foo/Cargo.toml".to_string()).unwrap();
+    assert_eq!(
+        toml.workspace_root(),
+        Some(fs::canonicalize("test/workspace").unwrap().as_path())
+    );
+    let mut pack_context = PackageContext::new();
+    toml.write_info_to_package_context(&mut pack_context)
+        .unwrap();
+    assert_eq!(pack_context.pack_info.version, "0.3.0");
+    assert_eq!(pack_context.pack_info.license, "MIT");
+    assert_eq!(pack_context.pack_info.authors, ["crate-spec"]);
+    assert_eq!(pack_context.pack_info.edition, "2021");
+    let serde = &pack_context.dep_infos[0];
+    assert_eq!(serde.ver_req, "1.0");
+    assert_eq!(serde.features, ["std", "derive"]);
+    assert!(serde.optional);
+    assert!(!serde.default_features);
+
+    assert!(matches!(
+        CrateToml::from_string("[package]\nname = \"foo\"\nversion.workspace = true"),
+        Err(Error::Manifest(_))
+    ));
+}
+
+#[test]
+fn test_toml_workspace_nested() {
+    let toml = CrateToml::from_file("test/workspace/crates/nested/Cargo.toml".to_string()).unwrap();
+    assert_eq!(
+        toml.workspace_root(),
+        Some(fs::canonicalize("test/workspace").unwrap().as_path())
+    );
+    assert_eq!(
+        toml.t["dependencies"]["bar"]["path"].as_str(),
+        Some("../../bar")
+    );
+    let mut pack_context = PackageContext::new();
+    toml.write_info_to_package_context(&mut pack_context)
+        .unwrap();
+    assert_eq!(pack_context.pack_info.version, "0.3.0");
+    assert_eq!(pack_context.pack_info.readme, "../../README.md");
+    assert_eq!(pack_context.pack_info.license_file, "../../LICENSE.txt");
+
+    let toml = CrateToml::from_file("test/workspace/excluded/Cargo.toml".to_string()).unwrap();
+    assert_eq!(toml.workspace_root(), None);
+    assert!(matches!(
+        CrateToml::from_file("test/workspace/stray/Cargo.toml".to_string()),
+        Err(Error::Manifest(_))
+    ));
 }
 
 #[test]
 fn test_toml() {
-    let toml = CrateToml::from_file("test/test.toml".to_string());
+    let toml = CrateToml::from_file("test/test.toml".to_string()).unwrap();
     let mut pack_context = PackageContext::new();
     println!(
         "{:?}",
         toml.write_info_to_package_context(&mut pack_context)
+            .unwrap()
     );
     println!("{:#?}", pack_context);
 }
@@ -1,6 +1,9 @@
 pub mod context;
 pub mod decode;
 pub mod encode;
+pub mod error;
+pub mod from_lock;
+pub mod from_metadata;
 pub mod from_toml;
 pub mod package;
 pub mod pkcs;
@@ -4,6 +4,7 @@ use std::io::{BufRead, Write};
 use std::mem::size_of;
 use std::vec;
 
+use crate::utils::error::Error;
 use crate::utils::package::gen_bincode::{create_bincode_slice_decoder, encode2vec_by_bincode};
 use crate::utils::package::*;
 
@@ -12,8 +13,8 @@ pub trait Encode {
     fn encode_to_vec(&self) -> Vec<u8> {
         self.encode()
     }
-    fn encode_to_writer<W: Write>(&self, writer: &mut W) -> usize {
-        writer.write(&self.encode()).unwrap()
+    fn encode_to_writer<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+        Ok(writer.write(&self.encode())?)
     }
 }
 
@@ -26,10 +27,10 @@ pub trait Decode {
     fn decode_from_slice(bin: &[u8]) -> Self::Output {
         Self::decode(bin)
     }
-    fn decode_from_reader<W: BufRead>(reader: &mut W, size: usize) -> Self::Output {
+    fn decode_from_reader<W: BufRead>(reader: &mut W, size: usize) -> Result<Self::Output, Error> {
         let mut buf = vec![0u8; size];
-        reader.read_exact(buf.as_mut_slice()).unwrap();
-        Self::decode(buf.as_mut_slice())
+        reader.read_exact(buf.as_mut_slice())?;
+        Ok(Self::decode(buf.as_mut_slice()))
     }
 }
 
@@ -40,16 +41,16 @@ impl<T: enc::Encode + 'static> Encode for RawArrayType<T> {
 }
 
 impl<T: enc::Encode + 'static + bincode::de::Decode> Decode for RawArrayType<T> {
-    type Output = RawArrayType<T>;
+    type Output = Result<RawArrayType<T>, Error>;
     fn decode(bin: &[u8]) -> Self::Output {
         let mut decoder = create_bincode_slice_decoder(bin);
         let mut output = RawArrayType::new();
         let len = bin.len() / size_of::<T>();
         for _i in 0..len {
-            output
-                .arr
-                .push(bincode::Decode::decode(&mut decoder).unwrap());
+            output.arr.push(
+                bincode::Decode::decode(&mut decoder).map_err(|e| Error::Format(e.to_string()))?,
+            );
         }
-        output
+        Ok(output)
     }
 }
@@ -2,16 +2,22 @@ use bincode;
 use bincode::config::{legacy, Configuration, Fixint, LittleEndian, NoLimit};
 use bincode::de::read::{Reader, SliceReader};
 use bincode::de::{Decoder, DecoderImpl};
+use bincode::enc::write::Writer;
 use bincode::enc::Encoder;
-use bincode::{enc, BorrowDecode, Decode, Encode};
+use bincode::{enc, Decode, Encode};
 
 use bincode::error::{DecodeError, EncodeError};
+use std::borrow::Cow;
 
+use crate::utils::error::Error;
+use crate::utils::package::view::ScrateView;
 use crate::utils::package::{
-    CrateBinarySection, CrateHeader, CratePackage, DataSection, DataSectionCollectionType,
-    DepTableSection, FingerPrintType, LenArrayType, MagicNumberType, PackageSection, RawArrayType,
-    SectionIndex, SectionIndexEntry, SigStructureSection, Size, Type, Uchar, FINGERPRINT_LEN,
-    MAGIC_NUMBER,
+    format_version, has_wide_offsets, is_critical, section_kind, CrateBinarySection, CrateHeader,
+    CratePackage, DataSection, DataSectionCollectionType, DepTableEntry, DepTableSection,
+    FeatureEntry, FeaturesSection, FingerPrintType, LenArrayType, LockfileEntry, LockfileSection,
+    PackageSection, RawArrayType, SectionIndex, SectionIndexEntry, SigStructureSection, Size,
+    TargetEntry, TargetsSection, Type, Uchar, UnknownSection, VcsInfoSection, FINGERPRINT_LEN,
+    MAGIC_NUMBER, MAGIC_NUMBER_LEN,
 };
 
 pub const BINCODE_CONFIG: Configuration<LittleEndian, Fixint, NoLimit> = legacy();
@@ -32,49 +38,253 @@ pub fn encode2vec_by_bincode<T: enc::Encode>(val: &T) -> Vec<u8> {
     buffer
 }
 
-pub fn decode_slice_by_bincode<T: bincode::de::Decode>(bin: &[u8]) -> T {
-    let (res, _) = bincode::decode_from_slice(bin, BINCODE_CONFIG).unwrap();
-    res
+pub fn decode_slice_by_bincode<T: bincode::de::Decode>(bin: &[u8]) -> Result<T, Error> {
+    let (res, _) = bincode::decode_from_slice(bin, BINCODE_CONFIG)
+        .map_err(|e| Error::Format(e.to_string()))?;
+    Ok(res)
 }
 
-pub fn create_bincode_slice_decoder(
-    bin: &[u8],
-) -> DecoderImpl<SliceReader, Configuration<LittleEndian, Fixint, NoLimit>> {
+pub type SliceDecoder<'a> =
+    DecoderImpl<SliceReader<'a>, Configuration<LittleEndian, Fixint, NoLimit>>;
+
+pub fn create_bincode_slice_decoder(bin: &[u8]) -> SliceDecoder<'_> {
     DecoderImpl::new(SliceReader::new(bin), BINCODE_CONFIG)
 }
 
+//===============layout===============
+
+///how a package is laid out, given by its c_version: the width of Off/Size and
+///LenArrayType lengths, and since c_version 5 the alignment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    c_version: Uchar,
+}
+
+impl Layout {
+    pub fn of(c_version: Uchar) -> Self {
+        Self { c_version }
+    }
+
+    ///Off/Size (and LenArrayType lengths) are 8 bytes instead of 4
+    pub fn offsets_are_wide(self) -> bool {
+        has_wide_offsets(self.c_version)
+    }
+
+    ///since c_version 5 the header fields, the section index, its entries' fields and
+    ///each data section start at a multiple of the offset width, 1 before
+    pub fn alignment(self) -> usize {
+        match self.c_version {
+            c_version if format_version(c_version) < 5 => 1,
+            _ if self.offsets_are_wide() => 8,
+            _ => 4,
+        }
+    }
+
+    pub fn align_up(self, off: usize) -> usize {
+        off.next_multiple_of(self.alignment())
+    }
+
+    ///bytes between c_version and the first header field, it follows the magic number
+    fn header_padding(self) -> usize {
+        self.align_up(MAGIC_NUMBER_LEN + 1) - (MAGIC_NUMBER_LEN + 1)
+    }
+
+    fn encode_word<E: Encoder>(self, val: u64, encoder: &mut E) -> Result<(), EncodeError> {
+        if self.offsets_are_wide() {
+            return val.encode(encoder);
+        }
+        u32::try_from(val)
+            .map_err(|_| EncodeError::Other("offset or size doesn't fit in 4 bytes"))?
+            .encode(encoder)
+    }
+
+    fn decode_word<D: Decoder>(self, decoder: &mut D) -> Result<u64, DecodeError> {
+        if self.offsets_are_wide() {
+            return Decode::decode(decoder);
+        }
+        Ok(u32::decode(decoder)? as u64)
+    }
+}
+
+///Encode of a structure whose encoding depends on the package's layout
+pub trait LayoutEncode {
+    fn encode_in<E: Encoder>(&self, encoder: &mut E, layout: Layout) -> Result<(), EncodeError>;
+}
+
+///Decode of a structure whose encoding depends on the package's layout
+pub trait LayoutDecode: Sized {
+    fn decode_in<D: Decoder>(decoder: &mut D, layout: Layout) -> Result<Self, DecodeError>;
+}
+
+pub fn encode_size_in<T: LayoutEncode>(val: &T, layout: Layout) -> usize {
+    let mut size_encoder = enc::EncoderImpl::new(enc::write::SizeWriter::default(), BINCODE_CONFIG);
+    val.encode_in(&mut size_encoder, layout).unwrap();
+    size_encoder.into_writer().bytes_written
+}
+
+pub fn encode2vec_in<T: LayoutEncode>(val: &T, layout: Layout) -> Vec<u8> {
+    let mut buffer = vec![0; encode_size_in(val, layout)];
+    let mut encoder = enc::EncoderImpl::new(
+        enc::write::SliceWriter::new(buffer.as_mut_slice()),
+        BINCODE_CONFIG,
+    );
+    val.encode_in(&mut encoder, layout).unwrap();
+    buffer
+}
+
+///types encoded the same in every layout
+macro_rules! layout_independent {
+    ($($ty:ty),*) => {
+        $(
+            impl LayoutEncode for $ty {
+                fn encode_in<E: Encoder>(&self, encoder: &mut E, _layout: Layout) -> Result<(), EncodeError> {
+                    self.encode(encoder)
+                }
+            }
+
+            impl LayoutDecode for $ty {
+                fn decode_in<D: Decoder>(decoder: &mut D, _layout: Layout) -> Result<Self, DecodeError> {
+                    Decode::decode(decoder)
+                }
+            }
+        )*
+    };
+}
+
+layout_independent!(u8, u32, LockfileEntry);
+
+///LayoutEncode of a struct, field by field
+macro_rules! layout_encode_fields {
+    ($ty:ty { $($field:ident),* }) => {
+        impl LayoutEncode for $ty {
+            fn encode_in<E: Encoder>(&self, encoder: &mut E, layout: Layout) -> Result<(), EncodeError> {
+                $(self.$field.encode_in(encoder, layout)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+///LayoutDecode of a struct, field by field
+macro_rules! layout_decode_fields {
+    ($ty:ty { $($field:ident),* }) => {
+        impl LayoutDecode for $ty {
+            fn decode_in<D: Decoder>(decoder: &mut D, layout: Layout) -> Result<Self, DecodeError> {
+                Ok(Self {
+                    $($field: LayoutDecode::decode_in(decoder, layout)?,)*
+                })
+            }
+        }
+    };
+}
+
+fn encode_padding<E: Encoder>(len: usize, encoder: &mut E) -> Result<(), EncodeError> {
+    for _i in 0..len {
+        0u8.encode(encoder)?;
+    }
+    Ok(())
+}
+
+///raw bytes, without a length
+fn encode_bytes<E: Encoder>(bytes: &[u8], encoder: &mut E) -> Result<(), EncodeError> {
+    encoder.writer().write(bytes)
+}
+
+///padding is zero so that a package has a single encoding
+fn decode_padding<D: Decoder>(len: usize, decoder: &mut D) -> Result<(), DecodeError> {
+    for _i in 0..len {
+        if u8::decode(decoder)? != 0 {
+            return Err(DecodeError::Other("file format not right! - padding"));
+        }
+    }
+    Ok(())
+}
+
 //===============custom Encode, Decode===============
 
-//LenArrayType Encode+Decode
-impl<T: Encode + 'static> Encode for LenArrayType<T> {
+//CrateHeader Encode+Decode, c_version says how the rest is laid out
+impl Encode for CrateHeader {
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
-        Encode::encode(&self.len, encoder)?;
-        for elem in self.arr.iter() {
-            elem.encode(encoder)?;
+        self.c_version.encode(encoder)?;
+        let layout = Layout::of(self.c_version);
+        encode_padding(layout.header_padding(), encoder)?;
+        layout.encode_word(self.strtable_size, encoder)?;
+        layout.encode_word(self.strtable_offset, encoder)?;
+        layout.encode_word(self.si_size, encoder)?;
+        layout.encode_word(self.si_offset, encoder)?;
+        layout.encode_word(self.si_num, encoder)?;
+        layout.encode_word(self.ds_offset, encoder)
+    }
+}
+
+impl Decode for CrateHeader {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let c_version: Uchar = Decode::decode(decoder)?;
+        let layout = Layout::of(c_version);
+        decode_padding(layout.header_padding(), decoder)?;
+        Ok(Self {
+            c_version,
+            strtable_size: layout.decode_word(decoder)?,
+            strtable_offset: layout.decode_word(decoder)?,
+            si_size: layout.decode_word(decoder)?,
+            si_offset: layout.decode_word(decoder)?,
+            si_num: layout.decode_word(decoder)?,
+            ds_offset: layout.decode_word(decoder)?,
+        })
+    }
+}
+
+//SectionIndexEntry Encode+Decode
+impl LayoutEncode for SectionIndexEntry {
+    fn encode_in<E: Encoder>(&self, encoder: &mut E, layout: Layout) -> Result<(), EncodeError> {
+        self.sh_type.encode(encoder)?;
+        encode_padding(layout.align_up(1) - 1, encoder)?;
+        layout.encode_word(self.sh_offset, encoder)?;
+        layout.encode_word(self.sh_size, encoder)
+    }
+}
+
+impl LayoutDecode for SectionIndexEntry {
+    fn decode_in<D: Decoder>(decoder: &mut D, layout: Layout) -> Result<Self, DecodeError> {
+        let sh_type = Decode::decode(decoder)?;
+        decode_padding(layout.align_up(1) - 1, decoder)?;
+        Ok(Self {
+            sh_type,
+            sh_offset: layout.decode_word(decoder)?,
+            sh_size: layout.decode_word(decoder)?,
+        })
+    }
+}
+
+//SectionIndex Encode
+impl LayoutEncode for SectionIndex {
+    fn encode_in<E: Encoder>(&self, encoder: &mut E, layout: Layout) -> Result<(), EncodeError> {
+        for entry in self.entries.arr.iter() {
+            entry.encode_in(encoder, layout)?;
         }
         Ok(())
     }
 }
 
-impl<T: Decode + 'static> Decode for LenArrayType<T> {
-    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
-        let mut len_array = LenArrayType::<T>::new();
-        len_array.len = bincode::Decode::decode(decoder)?;
-        for _i in 0..len_array.len {
-            len_array.arr.push(Decode::decode(decoder)?);
+//LenArrayType Encode+Decode
+impl<T: LayoutEncode + 'static> LayoutEncode for LenArrayType<T> {
+    fn encode_in<E: Encoder>(&self, encoder: &mut E, layout: Layout) -> Result<(), EncodeError> {
+        layout.encode_word(self.len, encoder)?;
+        for elem in self.arr.iter() {
+            elem.encode_in(encoder, layout)?;
         }
-        Ok(len_array)
+        Ok(())
     }
 }
 
-impl<'de, T: BorrowDecode<'de> + 'static> BorrowDecode<'de> for LenArrayType<T> {
-    fn borrow_decode<D: bincode::de::BorrowDecoder<'de>>(
-        decoder: &mut D,
-    ) -> Result<Self, DecodeError> {
+impl<T: LayoutDecode + 'static> LayoutDecode for LenArrayType<T> {
+    fn decode_in<D: Decoder>(decoder: &mut D, layout: Layout) -> Result<Self, DecodeError> {
         let mut len_array = LenArrayType::<T>::new();
-        len_array.len = bincode::BorrowDecode::borrow_decode(decoder)?;
+        len_array.len = layout.decode_word(decoder)?;
         for _i in 0..len_array.len {
-            len_array.arr.push(BorrowDecode::borrow_decode(decoder)?);
+            len_array
+                .arr
+                .push(LayoutDecode::decode_in(decoder, layout)?);
         }
         Ok(len_array)
     }
@@ -127,6 +337,46 @@ fn test_raw_array_type() {
     println!("{:?}", raw_array);
 }
 
+#[test]
+fn test_layout() {
+    use crate::utils::package::{CRATEVERSION, C_VERSION_WIDE};
+
+    let entry = SectionIndexEntry::new(3, 13, 7);
+    //c_version, alignment, entry size, bytes before the first header field
+    for (c_version, alignment, entry_size, header_fields) in [
+        (4, 1, 9, 6),
+        (4 | C_VERSION_WIDE, 1, 17, 6),
+        (CRATEVERSION, 4, 12, 8),
+        (CRATEVERSION | C_VERSION_WIDE, 8, 24, 8),
+    ] {
+        let layout = Layout::of(c_version);
+        assert_eq!(layout.alignment(), alignment);
+        assert_eq!(layout.align_up(13), 13usize.next_multiple_of(alignment));
+        assert_eq!(
+            MAGIC_NUMBER_LEN + 1 + layout.header_padding(),
+            header_fields
+        );
+
+        let bin = encode2vec_in(&entry, layout);
+        assert_eq!(bin.len(), entry_size);
+        let decoded =
+            SectionIndexEntry::decode_in(&mut create_bincode_slice_decoder(&bin), layout).unwrap();
+        assert_eq!(
+            (decoded.sh_type, decoded.sh_offset, decoded.sh_size),
+            (3, 13, 7)
+        );
+        //the padding after sh_type must be zero
+        if alignment > 1 {
+            let mut bad = bin.clone();
+            bad[1] = 1;
+            assert!(
+                SectionIndexEntry::decode_in(&mut create_bincode_slice_decoder(&bad), layout)
+                    .is_err()
+            );
+        }
+    }
+}
+
 // //PKCS7Struct Encode
 // impl Encode for PKCS7Struct{
 //     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
@@ -137,22 +387,48 @@ fn test_raw_array_type() {
 //     }
 // }
 
+//DataSectionCollectionType Encode, each section starts aligned
+impl LayoutEncode for DataSectionCollectionType<'_> {
+    fn encode_in<E: Encoder>(&self, encoder: &mut E, layout: Layout) -> Result<(), EncodeError> {
+        let mut end = 0;
+        for (section, (size, offset)) in self.col.arr.iter().zip(self.encode_size_offset(layout)) {
+            encode_padding(offset - end, encoder)?;
+            section.encode_in(encoder, layout)?;
+            end = offset + size;
+        }
+        Ok(())
+    }
+}
+
 //datasection Encode
-impl Encode for DataSection {
-    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+impl LayoutEncode for DataSection<'_> {
+    fn encode_in<E: Encoder>(&self, encoder: &mut E, layout: Layout) -> Result<(), EncodeError> {
         match &self {
-            DataSection::PackageSection(x) => x.encode(encoder)?,
-            DataSection::DepTableSection(x) => x.encode(encoder)?,
+            DataSection::PackageSection(x) => x.encode_in(encoder, layout)?,
+            DataSection::DepTableSection(x) => x.encode_in(encoder, layout)?,
             DataSection::CrateBinarySection(x) => x.encode(encoder)?,
-            DataSection::SigStructureSection(x) => x.encode(encoder)?, //_ => {panic!("section type error")}
+            DataSection::SigStructureSection(x) => x.encode_in(encoder, layout)?, //_ => {panic!("section type error")}
+            DataSection::VcsInfoSection(x) => x.encode(encoder)?,
+            DataSection::LockfileSection(x) => x.encode_in(encoder, layout)?,
+            DataSection::FeaturesSection(x) => x.encode_in(encoder, layout)?,
+            DataSection::TargetsSection(x) => x.encode_in(encoder, layout)?,
+            DataSection::Unknown(x) => encode_bytes(&x.bin, encoder)?,
         }
         Ok(())
     }
 }
 
-impl Decode for SigStructureSection {
-    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
-        let sigstruct_size: Size = Decode::decode(decoder)?;
+impl LayoutEncode for SigStructureSection {
+    fn encode_in<E: Encoder>(&self, encoder: &mut E, layout: Layout) -> Result<(), EncodeError> {
+        layout.encode_word(self.sigstruct_size, encoder)?;
+        self.sigstruct_type.encode(encoder)?;
+        self.sigstruct_sig.encode(encoder)
+    }
+}
+
+impl LayoutDecode for SigStructureSection {
+    fn decode_in<D: Decoder>(decoder: &mut D, layout: Layout) -> Result<Self, DecodeError> {
+        let sigstruct_size: Size = layout.decode_word(decoder)?;
         let sigstruct_type: Type = Decode::decode(decoder)?;
         let sigstruct_sig = RawArrayType::<u8>::decode(decoder, sigstruct_size as usize)?;
         //let sigstruct_sig:PKCS7Struct = PKCS7Struct::decode(decoder, sigstruct_size as usize)?;
@@ -163,16 +439,67 @@ impl Decode for SigStructureSection {
         })
     }
 }
-// non-self decode
 
-fn is_magic_number(mn: &MagicNumberType) -> bool {
-    for i in 0..MAGIC_NUMBER.len() {
-        if mn[i] != MAGIC_NUMBER[i] {
-            return false;
-        }
-    }
-    true
-}
+layout_encode_fields!(PackageSection {
+    pkg_name,
+    pkg_version,
+    pkg_license,
+    pkg_authors,
+    pkg_description,
+    pkg_documentation,
+    pkg_homepage,
+    pkg_repository,
+    pkg_readme,
+    pkg_keywords,
+    pkg_categories,
+    pkg_edition,
+    pkg_rust_version,
+    pkg_links,
+    pkg_license_file
+});
+
+layout_encode_fields!(DepTableEntry {
+    dep_name,
+    dep_verreq,
+    dep_srctype,
+    dep_srcpath,
+    dep_platform,
+    dep_kind,
+    dep_flags,
+    dep_package,
+    dep_gitreftype,
+    dep_gitref,
+    dep_features
+});
+
+layout_encode_fields!(DepTableSection { entries });
+layout_decode_fields!(DepTableSection { entries });
+layout_encode_fields!(LockfileSection { entries });
+layout_decode_fields!(LockfileSection { entries });
+layout_encode_fields!(FeatureEntry {
+    feat_name,
+    feat_items
+});
+layout_decode_fields!(FeatureEntry {
+    feat_name,
+    feat_items
+});
+layout_encode_fields!(FeaturesSection { entries });
+layout_decode_fields!(FeaturesSection { entries });
+layout_encode_fields!(TargetEntry {
+    target_name,
+    target_kinds,
+    target_path
+});
+layout_decode_fields!(TargetEntry {
+    target_name,
+    target_kinds,
+    target_path
+});
+layout_encode_fields!(TargetsSection { entries });
+layout_decode_fields!(TargetsSection { entries });
+
+// non-self decode
 
 macro_rules! early_return {
     ($condition:expr, $value:expr) => {
@@ -182,169 +509,219 @@ macro_rules! early_return {
     };
 }
 
-impl CratePackage {
+//CratePackage Encode, laid out as its header says
+impl Encode for CratePackage<'_> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        let layout = self.layout();
+        self.magic_number.encode(encoder)?;
+        self.crate_header.encode(encoder)?;
+        encode_bytes(&self.string_table, encoder)?;
+        let strtable_end =
+            (self.crate_header.strtable_offset + self.crate_header.strtable_size) as usize;
+        encode_padding(layout.align_up(strtable_end) - strtable_end, encoder)?;
+        self.section_index.encode_in(encoder, layout)?;
+        self.data_sections.encode_in(encoder, layout)?;
+        self.finger_print.encode(encoder)
+    }
+}
+
+//CrateBinarySection Encode
+impl Encode for CrateBinarySection<'_> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        encode_bytes(&self.bin, encoder)
+    }
+}
+
+impl<'a> CratePackage<'a> {
     pub fn encode_to_vec(&self) -> Vec<u8> {
         encode2vec_by_bincode(self)
     }
 
-    pub fn decode_from_slice(bin: &[u8]) -> Result<CratePackage, String> {
-        return match Self::decode(&mut create_bincode_slice_decoder(bin), bin) {
-            Ok(t) => Ok(t),
-            Err(DecodeError::Other(s)) => Err(s.to_string()),
-            Err(_) => Err("file format not right! - others".to_string()),
-        };
+    ///the layout its header's c_version says
+    pub fn layout(&self) -> Layout {
+        Layout::of(self.crate_header.c_version)
     }
 
-    pub fn decode<D: Decoder>(decoder: &mut D, bin: &[u8]) -> Result<Self, DecodeError> {
-        let magic_number: MagicNumberType = Decode::decode(decoder).unwrap();
-        if !is_magic_number(&magic_number) {
-            return Err(DecodeError::Other("magic not right!"));
+    pub fn decode_from_slice(bin: &'a [u8]) -> Result<Self, Error> {
+        let view = ScrateView::new(bin)?;
+        match Self::decode(&view) {
+            Ok(t) => Ok(t),
+            Err(DecodeError::Other(s)) => Err(Error::Format(s.to_string())),
+            Err(_) => Err(Error::Format("file format not right! - others".to_string())),
         }
+    }
 
-        let crate_header: CrateHeader = Decode::decode(decoder)?;
-
-        early_return!(
-            bin.len() > (crate_header.strtable_size + crate_header.strtable_offset) as usize,
-            "file format not right! - strtable"
-        );
-        let string_table_bin = &bin[crate_header.strtable_offset as usize
-            ..(crate_header.strtable_size + crate_header.strtable_offset) as usize];
-        let string_table: RawArrayType<Uchar> = RawArrayType::<Uchar>::decode(
-            &mut create_bincode_slice_decoder(string_table_bin),
-            string_table_bin.len(),
-        )?;
-
-        early_return!(
-            bin.len() > (crate_header.si_offset + crate_header.si_size) as usize,
-            "file format not right! - si"
-        );
-        let section_index_bin = &bin[crate_header.si_offset as usize
-            ..(crate_header.si_offset + crate_header.si_size) as usize];
-        let section_index: SectionIndex = SectionIndex::decode(
-            &mut create_bincode_slice_decoder(section_index_bin),
-            crate_header.si_num as usize,
-        )?;
-
-        let mut enum_size_off_in_bytes = vec![];
-        section_index.entries.arr.iter().for_each(|index_entry| {
-            enum_size_off_in_bytes.push((
-                index_entry.sh_type as i32,
-                index_entry.sh_size as usize,
-                index_entry.sh_offset as usize,
-            ))
-        });
-
-        early_return!(
-            bin.len() > crate_header.ds_offset as usize,
-            "file format not right! - ds"
-        );
-        let datasections_bin = &bin[crate_header.ds_offset as usize..];
-        let data_sections = DataSectionCollectionType::decode(
-            &mut create_bincode_slice_decoder(datasections_bin),
-            enum_size_off_in_bytes,
-        )?;
-
-        early_return!(
-            bin[bin.len() - FINGERPRINT_LEN..].len() == FINGERPRINT_LEN,
-            "file format not right! - fingerprint"
-        );
-        let fingerprint_bin = &bin[bin.len() - FINGERPRINT_LEN..];
-        let finger_print: FingerPrintType =
-            Decode::decode(&mut create_bincode_slice_decoder(fingerprint_bin))?;
-
+    ///decode the sections of a package whose layout `view` already checked,
+    ///the string table and the crate binary stay in `view`'s bytes
+    pub fn decode(view: &ScrateView<'a>) -> Result<Self, DecodeError> {
+        let data_sections = DataSectionCollectionType::decode(view)?;
+        let mut finger_print: FingerPrintType = [0; FINGERPRINT_LEN];
+        finger_print.copy_from_slice(view.finger_print());
         Ok(Self {
-            magic_number,
-            crate_header,
-            string_table,
-            section_index,
+            magic_number: MAGIC_NUMBER,
+            crate_header: view.header().clone(),
+            string_table: Cow::Borrowed(view.string_table()),
+            section_index: SectionIndex {
+                entries: RawArrayType::from_vec(view.section_index().entries.arr.clone()),
+            },
             data_sections,
             finger_print,
         })
     }
 }
 
+///decode `T` from exactly `bin`, trailing bytes are an error
+fn decode_exact<'a, T, F>(bin: &'a [u8], f: F) -> Result<T, DecodeError>
+where
+    F: FnOnce(&mut SliceDecoder<'a>) -> Result<T, DecodeError>,
+{
+    let mut decoder = create_bincode_slice_decoder(bin);
+    let val = f(&mut decoder)?;
+    early_return!(
+        decoder.reader().peek_read(1).is_none(),
+        "file format not right! - section size"
+    );
+    Ok(val)
+}
+
 ///SectionIndex Decode
 impl SectionIndex {
-    pub fn decode<D: Decoder>(decoder: &mut D, elem_num: usize) -> Result<Self, DecodeError> {
-        Ok(Self {
-            entries: RawArrayType::<SectionIndexEntry>::decode(decoder, elem_num)?,
-        })
+    pub fn decode<D: Decoder>(
+        decoder: &mut D,
+        elem_num: usize,
+        layout: Layout,
+    ) -> Result<Self, DecodeError> {
+        let mut entries = RawArrayType::new();
+        for _i in 0..elem_num {
+            entries
+                .arr
+                .push(SectionIndexEntry::decode_in(decoder, layout)?);
+        }
+        Ok(Self { entries })
     }
 }
 
 ///RawCollection Decode
-impl DataSectionCollectionType {
-    pub fn decode<D: Decoder>(
-        decoder: &mut D,
-        enum_size_offset_in_bytes: Vec<(i32, usize, usize)>,
-    ) -> Result<Self, DecodeError> {
+impl<'a> DataSectionCollectionType<'a> {
+    pub fn decode(view: &ScrateView<'a>) -> Result<Self, DecodeError> {
+        let layout = view.layout();
         let mut raw_col = DataSectionCollectionType::new();
-        let mut consume_size = 0;
-        for (type_id, size, offset) in enum_size_offset_in_bytes.into_iter() {
-            if consume_size > offset {
-                return Err(DecodeError::Other("file format not right!"));
-            }
-            if consume_size < offset {
-                decoder.reader().consume(offset - consume_size);
-                consume_size = offset;
-            }
-            match type_id {
-                0 => {
-                    let pack_sec: PackageSection = Decode::decode(decoder)?;
-                    raw_col.col.arr.push(DataSection::PackageSection(pack_sec));
-                }
-                1 => {
-                    let dep_table: DepTableSection = Decode::decode(decoder)?;
-                    raw_col
-                        .col
-                        .arr
-                        .push(DataSection::DepTableSection(dep_table));
-                }
-                3 => {
-                    let crate_binary: CrateBinarySection =
-                        CrateBinarySection::decode(decoder, size)?;
-                    raw_col
-                        .col
-                        .arr
-                        .push(DataSection::CrateBinarySection(crate_binary));
-                }
-                4 => {
-                    let sig_structure: SigStructureSection = Decode::decode(decoder)?;
-                    raw_col
-                        .col
-                        .arr
-                        .push(DataSection::SigStructureSection(sig_structure));
+        for (id, entry) in view.section_index().entries.arr.iter().enumerate() {
+            let section_bin = view
+                .section(id)
+                .ok_or(DecodeError::Other("file format not right! - ds"))?;
+            let section = match section_kind(entry.sh_type) {
+                0 => DataSection::PackageSection(decode_exact(section_bin, |d| {
+                    PackageSection::decode_in(d, layout)
+                })?),
+                1 => DataSection::DepTableSection(decode_exact(section_bin, |d| {
+                    DepTableSection::decode_in(d, layout)
+                })?),
+                3 => DataSection::CrateBinarySection(CrateBinarySection {
+                    bin: Cow::Borrowed(section_bin),
+                }),
+                4 => DataSection::SigStructureSection(decode_exact(section_bin, |d| {
+                    SigStructureSection::decode_in(d, layout)
+                })?),
+                5 => DataSection::VcsInfoSection(decode_exact(section_bin, Decode::decode)?),
+                6 => DataSection::LockfileSection(decode_exact(section_bin, |d| {
+                    LockfileSection::decode_in(d, layout)
+                })?),
+                7 => DataSection::FeaturesSection(decode_exact(section_bin, |d| {
+                    FeaturesSection::decode_in(d, layout)
+                })?),
+                8 => DataSection::TargetsSection(decode_exact(section_bin, |d| {
+                    TargetsSection::decode_in(d, layout)
+                })?),
+                _ if !is_critical(entry.sh_type) => DataSection::Unknown(UnknownSection {
+                    sh_type: entry.sh_type,
+                    bin: Cow::Borrowed(section_bin),
+                }),
+                _ => {
+                    return Err(DecodeError::Other(
+                        "file format not right! - unknown critical section type",
+                    ))
                 }
-                _ => return Err(DecodeError::Other("file format not right!")),
-            }
-            consume_size += size;
+            };
+            raw_col.col.arr.push(section);
         }
         Ok(raw_col)
     }
 
-    pub fn encode_size_offset(&self) -> Vec<(usize, usize)> {
+    pub fn encode_size_offset(&self, layout: Layout) -> Vec<(usize, usize)> {
         let mut v = vec![];
         let mut offset: usize = 0;
         self.col.arr.iter().for_each(|x| {
-            let size = encode_size_by_bincode(x);
+            let size = encode_size_in(x, layout);
+            offset = layout.align_up(offset);
             v.push((size, offset));
             offset += size;
         });
         v
     }
 
-    pub fn encode_fake_to_vec(&self, trunc_len: usize) -> Vec<u8> {
-        let mut buf = encode2vec_by_bincode(self);
+    pub fn encode_fake_to_vec(&self, trunc_len: usize, layout: Layout) -> Vec<u8> {
+        let mut buf = encode2vec_in(self, layout);
         buf.truncate(trunc_len);
         buf
     }
 }
 
+//PackageSection decode, the fields depend on the format version
+impl LayoutDecode for PackageSection {
+    fn decode_in<D: Decoder>(decoder: &mut D, layout: Layout) -> Result<Self, DecodeError> {
+        let c_version = format_version(layout.c_version);
+        let mut package = PackageSection::new();
+        package.pkg_name = Decode::decode(decoder)?;
+        package.pkg_version = Decode::decode(decoder)?;
+        package.pkg_license = Decode::decode(decoder)?;
+        package.pkg_authors = LayoutDecode::decode_in(decoder, layout)?;
+        if c_version >= 4 {
+            package.pkg_description = Decode::decode(decoder)?;
+            package.pkg_documentation = Decode::decode(decoder)?;
+            package.pkg_homepage = Decode::decode(decoder)?;
+            package.pkg_repository = Decode::decode(decoder)?;
+            package.pkg_readme = Decode::decode(decoder)?;
+            package.pkg_keywords = LayoutDecode::decode_in(decoder, layout)?;
+            package.pkg_categories = LayoutDecode::decode_in(decoder, layout)?;
+            package.pkg_edition = Decode::decode(decoder)?;
+            package.pkg_rust_version = Decode::decode(decoder)?;
+            package.pkg_links = Decode::decode(decoder)?;
+            package.pkg_license_file = Decode::decode(decoder)?;
+        }
+        Ok(package)
+    }
+}
+
+//DepTableEntry decode, the fields depend on the format version
+impl LayoutDecode for DepTableEntry {
+    fn decode_in<D: Decoder>(decoder: &mut D, layout: Layout) -> Result<Self, DecodeError> {
+        let c_version = format_version(layout.c_version);
+        let mut entry = DepTableEntry::new();
+        entry.dep_name = Decode::decode(decoder)?;
+        entry.dep_verreq = Decode::decode(decoder)?;
+        entry.dep_srctype = Decode::decode(decoder)?;
+        entry.dep_srcpath = Decode::decode(decoder)?;
+        entry.dep_platform = Decode::decode(decoder)?;
+        if c_version >= 2 {
+            entry.dep_kind = Decode::decode(decoder)?;
+        }
+        if c_version >= 3 {
+            entry.dep_flags = Decode::decode(decoder)?;
+            entry.dep_package = Decode::decode(decoder)?;
+            entry.dep_gitreftype = Decode::decode(decoder)?;
+            entry.dep_gitref = Decode::decode(decoder)?;
+            entry.dep_features = LayoutDecode::decode_in(decoder, layout)?;
+        }
+        Ok(entry)
+    }
+}
+
 //CrateBinarySection decode
-impl CrateBinarySection {
+impl CrateBinarySection<'_> {
     pub fn decode<D: Decoder>(decoder: &mut D, size_in_bytes: usize) -> Result<Self, DecodeError> {
         let mut dep_table = CrateBinarySection::new();
-        dep_table.bin = RawArrayType::<Uchar>::decode(decoder, size_in_bytes)?;
+        dep_table.bin = Cow::Owned(RawArrayType::<Uchar>::decode(decoder, size_in_bytes)?.arr);
         Ok(dep_table)
     }
 }
@@ -375,19 +752,19 @@ impl<T: Encode + 'static> RawArrayType<T> {
 }
 
 impl SectionIndex {
-    pub fn size(&self) -> usize {
-        encode_size_by_bincode(self)
+    pub fn size(&self, layout: Layout) -> usize {
+        encode_size_in(self, layout)
     }
 
     pub fn num(&self) -> usize {
         self.entries.arr.len()
     }
 
-    pub fn none_sig_size(&self) -> usize {
+    pub fn none_sig_size(&self, layout: Layout) -> usize {
         let mut total_len = 0;
         self.entries.arr.iter().for_each(|x| {
             if x.sh_type != 4 {
-                total_len += x.size();
+                total_len += x.size(layout);
             }
         });
         total_len
@@ -407,55 +784,84 @@ impl SectionIndex {
         self.num() - self.none_sig_num()
     }
 
-    pub fn sig_size(&self) -> usize {
-        self.size() - self.none_sig_size()
+    pub fn sig_size(&self, layout: Layout) -> usize {
+        self.size(layout) - self.none_sig_size(layout)
     }
 
-    pub fn encode_fake_to_vec(&self, no_sig_size: usize, size: usize) -> Vec<u8> {
-        let mut buf = encode2vec_by_bincode(self);
+    pub fn encode_fake_to_vec(&self, no_sig_size: usize, size: usize, layout: Layout) -> Vec<u8> {
+        let mut buf = encode2vec_in(self, layout);
         buf.truncate(no_sig_size);
         buf.extend(vec![0; size - no_sig_size]);
         buf
     }
 
     pub fn datasection_size_without_sig(&self) -> usize {
-        (self.entries.arr[self.none_sig_num() - 1].sh_offset
-            + self.entries.arr[self.none_sig_num() - 1].sh_size) as usize
+        match self.none_sig_num().checked_sub(1) {
+            Some(last) => {
+                let entry = &self.entries.arr[last];
+                entry.sh_offset as usize + entry.sh_size as usize
+            }
+            None => 0,
+        }
     }
 }
 
-impl DataSectionCollectionType {
-    pub fn size(&self) -> usize {
-        encode_size_by_bincode(self)
+impl DataSectionCollectionType<'_> {
+    pub fn size(&self, layout: Layout) -> usize {
+        encode_size_in(self, layout)
     }
 }
 
 impl PackageSection {
-    pub fn size(&self) -> usize {
-        encode_size_by_bincode(self)
+    pub fn size(&self, layout: Layout) -> usize {
+        encode_size_in(self, layout)
     }
 }
 
 impl DepTableSection {
-    pub fn size(&self) -> usize {
-        encode_size_by_bincode(self)
+    pub fn size(&self, layout: Layout) -> usize {
+        encode_size_in(self, layout)
     }
 }
 
-impl CrateBinarySection {
+impl CrateBinarySection<'_> {
     pub fn size(&self) -> usize {
-        encode_size_by_bincode(self)
+        self.bin.len()
     }
 }
 
 impl SigStructureSection {
+    pub fn size(&self, layout: Layout) -> usize {
+        encode_size_in(self, layout)
+    }
+}
+
+impl VcsInfoSection {
     pub fn size(&self) -> usize {
         encode_size_by_bincode(self)
     }
 }
 
+impl LockfileSection {
+    pub fn size(&self, layout: Layout) -> usize {
+        encode_size_in(self, layout)
+    }
+}
+
+impl FeaturesSection {
+    pub fn size(&self, layout: Layout) -> usize {
+        encode_size_in(self, layout)
+    }
+}
+
+impl TargetsSection {
+    pub fn size(&self, layout: Layout) -> usize {
+        encode_size_in(self, layout)
+    }
+}
+
 impl SectionIndexEntry {
-    pub fn size(&self) -> usize {
-        encode_size_by_bincode(self)
+    pub fn size(&self, layout: Layout) -> usize {
+        encode_size_in(self, layout)
     }
 }
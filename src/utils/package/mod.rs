@@ -1,16 +1,19 @@
 //!package definition
 pub mod bin;
 pub mod gen_bincode;
+pub mod view;
 
 use bincode::{Decode, Encode};
+use serde::Serialize;
+use std::borrow::Cow;
 
 //Types used in CratePackage
 
-///Unsigned file offset
-pub type Off = u32;
+///Unsigned file offset, 4 bytes in the file or 8 with C_VERSION_WIDE
+pub type Off = u64;
 
-///Unsigned file size
-pub type Size = u32;
+///Unsigned file size, 4 bytes in the file or 8 with C_VERSION_WIDE
+pub type Size = u64;
 
 ///Unsigned type id
 pub type Type = u8;
@@ -53,7 +56,7 @@ impl<T> Default for LenArrayType<T> {
 }
 
 impl<T: Clone> LenArrayType<T> {
-    pub fn copy_from_vec(v: &Vec<T>) -> Self {
+    pub fn copy_from_vec(v: &[T]) -> Self {
         let mut len_array = Self::new();
         len_array.arr = v.to_vec();
         len_array.len = v.len() as Size;
@@ -68,7 +71,7 @@ impl<T: Clone> LenArrayType<T> {
 /// array
 /// custom Encode
 /// non-self Decode
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RawArrayType<T> {
     pub arr: Vec<T>,
 }
@@ -89,15 +92,15 @@ impl<T> Default for RawArrayType<T> {
     }
 }
 
-/// auto encode
+/// custom encode
 /// self decode
 /// collections(array whose elem is enum)
-#[derive(Encode, Debug)]
-pub struct DataSectionCollectionType {
-    pub col: RawArrayType<DataSection>,
+#[derive(Debug)]
+pub struct DataSectionCollectionType<'a> {
+    pub col: RawArrayType<DataSection<'a>>,
 }
 
-impl DataSectionCollectionType {
+impl DataSectionCollectionType<'_> {
     pub fn new() -> Self {
         Self {
             col: RawArrayType::new(),
@@ -105,7 +108,7 @@ impl DataSectionCollectionType {
     }
 }
 
-impl Default for DataSectionCollectionType {
+impl Default for DataSectionCollectionType<'_> {
     fn default() -> Self {
         Self::new()
     }
@@ -135,29 +138,51 @@ pub const FINGERPRINT_LEN: usize = 32;
 
 pub type FingerPrintType = [Uchar; FINGERPRINT_LEN];
 
-pub const CRATEVERSION: Uchar = 0;
+///1: file signatures no longer cover the signature entries' share of the header
+///2: dependency table entries carry the dependency kind
+///3: dependency table entries carry flags, renamed package, git ref and features
+///4: the package section carries the rest of `[package]`, description to license-file
+///5: aligned layout, see gen_bincode::Layout
+pub const CRATEVERSION: Uchar = 5;
+
+///set in `c_version` of a package whose offsets and sizes are 8 bytes, the other 7 bits
+///are the version; only used when the package doesn't fit 4-byte ones
+pub const C_VERSION_WIDE: Uchar = 0x80;
+
+///`c_version` without the wide flag
+pub fn format_version(c_version: Uchar) -> Uchar {
+    c_version & !C_VERSION_WIDE
+}
+
+pub fn has_wide_offsets(c_version: Uchar) -> bool {
+    c_version & C_VERSION_WIDE != 0
+}
 
 //package structure
 
-//auto encode
+//custom encode
 //non-self decode
-///top-level package structure
-#[derive(Encode, Debug)]
-pub struct CratePackage {
+///top-level package structure, decoded from a file it borrows the string table
+///and the crate binary from it
+#[derive(Debug)]
+pub struct CratePackage<'a> {
     pub magic_number: MagicNumberType,
     pub crate_header: CrateHeader,
-    pub string_table: RawArrayType<Uchar>,
+    pub string_table: Cow<'a, [u8]>,
     pub section_index: SectionIndex,
-    pub data_sections: DataSectionCollectionType,
+    pub data_sections: DataSectionCollectionType<'a>,
     pub finger_print: FingerPrintType,
 }
 
-impl CratePackage {
+impl CratePackage<'_> {
     pub fn new() -> Self {
         Self {
             magic_number: MAGIC_NUMBER,
-            crate_header: CrateHeader::new(),
-            string_table: RawArrayType::new(),
+            crate_header: CrateHeader {
+                c_version: CRATEVERSION,
+                ..CrateHeader::new()
+            },
+            string_table: Cow::Borrowed(&[]),
             section_index: SectionIndex::new(),
             data_sections: DataSectionCollectionType::new(),
             finger_print: [0; FINGERPRINT_LEN],
@@ -165,16 +190,16 @@ impl CratePackage {
     }
 }
 
-impl Default for CratePackage {
+impl Default for CratePackage<'_> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-//auto encode
-//auto decode
+//custom encode
+//custom decode
 ///crate header structure
-#[derive(Encode, Decode, Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CrateHeader {
     pub c_version: Uchar,
     pub strtable_size: Size,
@@ -209,10 +234,10 @@ impl Default for CrateHeader {
     }
 }
 
-//auto encode
-//self decode
+//custom encode
+//non-self decode
 ///section index structure
-#[derive(Encode, Debug)]
+#[derive(Debug)]
 pub struct SectionIndex {
     pub entries: RawArrayType<SectionIndexEntry>,
 }
@@ -235,17 +260,12 @@ impl Default for SectionIndex {
     }
 }
 
-//auto encode
-//auto decode
-///section index entry structure
-#[derive(Encode, Decode, Debug, Default)]
+//custom encode
+//custom decode
+///section index entry structure, 9 bytes or 17 in a wide package;
+///since c_version 5 sh_type is padded to the offset width, 12 or 24 bytes
+#[derive(Debug, Default, Clone)]
 pub struct SectionIndexEntry {
-    /*
-    FIXME In RFC0.1 there are no alignment requirements for the struct.
-    SectionIndexEntry's size is 9 bytes, 9 is not a multiple of 4
-    we may padding 3 bytes of 0 after each SectionIndexEntry,
-    so here, we use a user-defined encoder to implement the serialization of SectionIndex.
-      */
     pub sh_type: Type,
     pub sh_offset: Off,
     pub sh_size: Size,
@@ -261,19 +281,42 @@ impl SectionIndexEntry {
     }
 }
 
+///set in `sh_type` of a section readers may skip if they don't know its type,
+///the other 7 bits are the type; unknown sections without it are rejected
+pub const SECTION_NONCRITICAL: Type = 0x80;
+
+///section type without the non-critical flag
+pub fn section_kind(sh_type: Type) -> Type {
+    sh_type & !SECTION_NONCRITICAL
+}
+
+pub fn is_critical(sh_type: Type) -> bool {
+    sh_type & SECTION_NONCRITICAL == 0
+}
+
 //custom encode
 //non-self decode
 //data sections
 #[derive(Debug)]
-pub enum DataSection {
+pub enum DataSection<'a> {
     //0
     PackageSection(PackageSection),
     //1
     DepTableSection(DepTableSection),
     //3
-    CrateBinarySection(CrateBinarySection),
+    CrateBinarySection(CrateBinarySection<'a>),
     //4
     SigStructureSection(SigStructureSection),
+    //5, optional
+    VcsInfoSection(VcsInfoSection),
+    //6, optional
+    LockfileSection(LockfileSection),
+    //7, optional
+    FeaturesSection(FeaturesSection),
+    //8, optional
+    TargetsSection(TargetsSection),
+    //a non-critical type this version doesn't know, kept as is
+    Unknown(UnknownSection<'a>),
 }
 
 pub fn datasection_type(d: &DataSection) -> Type {
@@ -282,18 +325,59 @@ pub fn datasection_type(d: &DataSection) -> Type {
         DataSection::DepTableSection(_) => 1,
         DataSection::CrateBinarySection(_) => 3,
         DataSection::SigStructureSection(_) => 4,
+        DataSection::VcsInfoSection(_) => 5 | SECTION_NONCRITICAL,
+        DataSection::LockfileSection(_) => 6 | SECTION_NONCRITICAL,
+        DataSection::FeaturesSection(_) => 7 | SECTION_NONCRITICAL,
+        DataSection::TargetsSection(_) => 8 | SECTION_NONCRITICAL,
+        DataSection::Unknown(x) => x.sh_type,
     }
 }
 
-//auto encode
-//auto decode
-///package section structure
-#[derive(Encode, Decode, Debug)]
+pub fn datasection_name(typ: Type) -> &'static str {
+    match section_kind(typ) {
+        0 => "package",
+        1 => "dep-table",
+        3 => "crate-binary",
+        4 => "sig-structure",
+        5 => "vcs-info",
+        6 => "lockfile",
+        7 => "features",
+        8 => "targets",
+        _ => "unknown",
+    }
+}
+
+//custom encode
+//custom decode
+///package section structure, optional fields are empty strings/arrays when unset
+#[derive(Debug)]
 pub struct PackageSection {
     pub pkg_name: StrOff,
     pub pkg_version: StrOff,
     pub pkg_license: StrOff,
     pub pkg_authors: LenArrayType<StrOff>,
+    ///since c_version 4
+    pub pkg_description: StrOff,
+    ///since c_version 4
+    pub pkg_documentation: StrOff,
+    ///since c_version 4
+    pub pkg_homepage: StrOff,
+    ///since c_version 4
+    pub pkg_repository: StrOff,
+    ///since c_version 4
+    pub pkg_readme: StrOff,
+    ///since c_version 4
+    pub pkg_keywords: LenArrayType<StrOff>,
+    ///since c_version 4
+    pub pkg_categories: LenArrayType<StrOff>,
+    ///since c_version 4
+    pub pkg_edition: StrOff,
+    ///since c_version 4
+    pub pkg_rust_version: StrOff,
+    ///since c_version 4
+    pub pkg_links: StrOff,
+    ///since c_version 4
+    pub pkg_license_file: StrOff,
 }
 
 impl PackageSection {
@@ -303,6 +387,17 @@ impl PackageSection {
             pkg_version: 0,
             pkg_license: 0,
             pkg_authors: LenArrayType::new(),
+            pkg_description: 0,
+            pkg_documentation: 0,
+            pkg_homepage: 0,
+            pkg_repository: 0,
+            pkg_readme: 0,
+            pkg_keywords: LenArrayType::new(),
+            pkg_categories: LenArrayType::new(),
+            pkg_edition: 0,
+            pkg_rust_version: 0,
+            pkg_links: 0,
+            pkg_license_file: 0,
         }
     }
 }
@@ -313,17 +408,34 @@ impl Default for PackageSection {
     }
 }
 
-//auto encode
-//auto decode
+//custom encode
+//custom decode
 ///Dependency table entry structure
-#[derive(Encode, Decode, Debug)]
+#[derive(Debug)]
 pub struct DepTableEntry {
     pub dep_name: StrOff,
     pub dep_verreq: StrOff,
     pub dep_srctype: Type,
     pub dep_srcpath: StrOff,
     pub dep_platform: StrOff,
-}
+    ///0 normal, 1 dev, 2 build; since c_version 2
+    pub dep_kind: Type,
+    ///DEP_FLAG_*; since c_version 3
+    pub dep_flags: Uchar,
+    ///real package name of a renamed dependency, empty if not renamed; since c_version 3
+    pub dep_package: StrOff,
+    ///0 none, 1 branch, 2 tag, 3 rev; since c_version 3
+    pub dep_gitreftype: Type,
+    ///since c_version 3
+    pub dep_gitref: StrOff,
+    ///since c_version 3
+    pub dep_features: LenArrayType<StrOff>,
+}
+
+///the dependency is optional
+pub const DEP_FLAG_OPTIONAL: Uchar = 1;
+///`default-features = false`
+pub const DEP_FLAG_NO_DEFAULT_FEATURES: Uchar = 2;
 
 impl DepTableEntry {
     pub fn new() -> Self {
@@ -333,6 +445,12 @@ impl DepTableEntry {
             dep_srctype: 0,
             dep_srcpath: 0,
             dep_platform: 0,
+            dep_kind: 0,
+            dep_flags: 0,
+            dep_package: 0,
+            dep_gitreftype: 0,
+            dep_gitref: 0,
+            dep_features: LenArrayType::new(),
         }
     }
 }
@@ -343,10 +461,10 @@ impl Default for DepTableEntry {
     }
 }
 
-//auto encode
-//non-self decode
+//custom encode
+//custom decode
 ///Dependency table section structure
-#[derive(Encode, Decode, Debug)]
+#[derive(Debug)]
 pub struct DepTableSection {
     pub entries: LenArrayType<DepTableEntry>,
 }
@@ -365,31 +483,31 @@ impl Default for DepTableSection {
     }
 }
 
-//auto encode
+//custom encode
 //non-self decode
-#[derive(Encode, Debug)]
-pub struct CrateBinarySection {
-    pub bin: RawArrayType<Uchar>,
+#[derive(Debug)]
+pub struct CrateBinarySection<'a> {
+    pub bin: Cow<'a, [u8]>,
 }
 
-impl CrateBinarySection {
+impl CrateBinarySection<'_> {
     pub fn new() -> Self {
         Self {
-            bin: RawArrayType::new(),
+            bin: Cow::Borrowed(&[]),
         }
     }
 }
 
-impl Default for CrateBinarySection {
+impl Default for CrateBinarySection<'_> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-//auto encode
+//custom encode
 //custom decode
 ///Signature  section structure
-#[derive(Encode, Debug)]
+#[derive(Debug)]
 pub struct SigStructureSection {
     pub sigstruct_size: Size,
     pub sigstruct_type: Type,
@@ -411,3 +529,145 @@ impl Default for SigStructureSection {
         Self::new()
     }
 }
+
+//auto encode
+//auto decode
+///VCS info section structure, where the packaged source came from
+#[derive(Encode, Decode, Debug)]
+pub struct VcsInfoSection {
+    ///0 git
+    pub vcs_type: Type,
+    pub vcs_commit: StrOff,
+    ///1 if the working tree had uncommitted changes
+    pub vcs_dirty: Uchar,
+    ///directory of the package inside the repository
+    pub vcs_path: StrOff,
+}
+
+impl VcsInfoSection {
+    pub fn new() -> Self {
+        Self {
+            vcs_type: 0,
+            vcs_commit: 0,
+            vcs_dirty: 0,
+            vcs_path: 0,
+        }
+    }
+}
+
+impl Default for VcsInfoSection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//auto encode
+//auto decode
+///a package of the resolved dependency graph, as pinned by Cargo.lock
+#[derive(Encode, Decode, Debug, Default)]
+pub struct LockfileEntry {
+    pub lock_name: StrOff,
+    pub lock_version: StrOff,
+    ///empty for path dependencies
+    pub lock_source: StrOff,
+    ///empty if Cargo.lock has none (path and git dependencies)
+    pub lock_checksum: StrOff,
+}
+
+//custom encode
+//custom decode
+///lockfile section structure
+#[derive(Debug)]
+pub struct LockfileSection {
+    pub entries: LenArrayType<LockfileEntry>,
+}
+
+impl LockfileSection {
+    pub fn new() -> Self {
+        Self {
+            entries: LenArrayType::new(),
+        }
+    }
+}
+
+impl Default for LockfileSection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//custom encode
+//custom decode
+///a `[features]` entry
+#[derive(Debug, Default)]
+pub struct FeatureEntry {
+    pub feat_name: StrOff,
+    ///features, `dep:<name>` and `<dep>/<feature>` it enables, as written
+    pub feat_items: LenArrayType<StrOff>,
+}
+
+//custom encode
+//custom decode
+///features section structure
+#[derive(Debug)]
+pub struct FeaturesSection {
+    pub entries: LenArrayType<FeatureEntry>,
+}
+
+impl FeaturesSection {
+    pub fn new() -> Self {
+        Self {
+            entries: LenArrayType::new(),
+        }
+    }
+}
+
+impl Default for FeaturesSection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//custom encode
+//custom decode
+///a lib or bin target
+#[derive(Debug, Default)]
+pub struct TargetEntry {
+    pub target_name: StrOff,
+    ///`bin`, or the lib kinds such as `lib`, `cdylib` and `proc-macro`
+    pub target_kinds: LenArrayType<StrOff>,
+    ///entry point, relative to the package root
+    pub target_path: StrOff,
+}
+
+//custom encode
+//custom decode
+///targets section structure
+#[derive(Debug)]
+pub struct TargetsSection {
+    pub entries: LenArrayType<TargetEntry>,
+}
+
+impl TargetsSection {
+    pub fn new() -> Self {
+        Self {
+            entries: LenArrayType::new(),
+        }
+    }
+}
+
+impl Default for TargetsSection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//custom encode
+//non-self decode
+///a section of a type this version doesn't know, its bytes are kept untouched
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownSection<'a> {
+    ///always has SECTION_NONCRITICAL set
+    pub sh_type: Type,
+    pub bin: Cow<'a, [u8]>,
+}
@@ -0,0 +1,277 @@
+//!borrowing reader of `.scrate` files
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::utils::error::Error;
+use crate::utils::package::gen_bincode::{create_bincode_slice_decoder, Layout};
+use crate::utils::package::{
+    format_version, section_kind, CrateHeader, SectionIndex, SectionIndexEntry, Type, CRATEVERSION,
+    FINGERPRINT_LEN, MAGIC_NUMBER, MAGIC_NUMBER_LEN,
+};
+use crate::utils::pkcs::PKCS;
+use bincode::Decode;
+
+fn check(condition: bool, msg: &str) -> Result<(), Error> {
+    match condition {
+        true => Ok(()),
+        false => Err(Error::Format(format!("file format not right! - {}", msg))),
+    }
+}
+
+///`bin[offset..offset + size]`, or None if it overflows or runs past the end
+fn checked_range(bin: &[u8], offset: usize, size: usize) -> Option<&[u8]> {
+    bin.get(offset..offset.checked_add(size)?)
+}
+
+///a `.scrate` file whose layout has been checked: header, string table, section index and
+///section payloads are slices of the file, nothing is copied and sections aren't decoded
+#[derive(Debug)]
+pub struct ScrateView<'a> {
+    bin: &'a [u8],
+    header: CrateHeader,
+    section_index: SectionIndex,
+}
+
+impl<'a> ScrateView<'a> {
+    pub fn new(bin: &'a [u8]) -> Result<Self, Error> {
+        if !bin.starts_with(&MAGIC_NUMBER) {
+            return Err(Error::BadMagic);
+        }
+        //a newer layout can't be told from a broken one, say which it is up front
+        //c_version is the header's first byte whatever the layout
+        if let Some(&c_version) = bin.get(MAGIC_NUMBER_LEN) {
+            if format_version(c_version) > CRATEVERSION {
+                return Err(Error::UnsupportedVersion(format_version(c_version)));
+            }
+        }
+        let header: CrateHeader =
+            Decode::decode(&mut create_bincode_slice_decoder(&bin[MAGIC_NUMBER_LEN..]))
+                .map_err(|_| Error::Format("file format not right! - header".to_string()))?;
+        let layout = Layout::of(header.c_version);
+
+        //everything but the fingerprint lives in body
+        check(
+            bin.len() >= MAGIC_NUMBER_LEN + header.size() + FINGERPRINT_LEN,
+            "fingerprint",
+        )?;
+        let body = &bin[..bin.len() - FINGERPRINT_LEN];
+
+        //the string table follows the header, the section index follows the string table
+        //and the data sections follow the section index, so a package has a single layout
+        check(
+            header.strtable_offset == (MAGIC_NUMBER_LEN + header.size()) as u64,
+            "strtable offset",
+        )?;
+        let strtable_end = checked_range(
+            body,
+            header.strtable_offset as usize,
+            header.strtable_size as usize,
+        )
+        .map(|strtable| header.strtable_offset as usize + strtable.len())
+        .ok_or_else(|| Error::Format("file format not right! - strtable".to_string()))?;
+
+        //an aligned layout has zero padding up to an aligned section index,
+        //its size is a multiple of the alignment so the data sections start aligned too
+        check(
+            header.si_offset == layout.align_up(strtable_end) as u64
+                && body
+                    .get(strtable_end..header.si_offset as usize)
+                    .is_some_and(|padding| padding.iter().all(|b| *b == 0)),
+            "si offset",
+        )?;
+
+        let section_index_bin =
+            checked_range(body, header.si_offset as usize, header.si_size as usize)
+                .ok_or_else(|| Error::Format("file format not right! - si".to_string()))?;
+        check(
+            header.ds_offset == header.si_offset + section_index_bin.len() as u64,
+            "ds offset",
+        )?;
+        check(
+            (header.si_num as usize).checked_mul(SectionIndexEntry::default().size(layout))
+                == Some(section_index_bin.len()),
+            "si num",
+        )?;
+        let section_index = SectionIndex::decode(
+            &mut create_bincode_slice_decoder(section_index_bin),
+            header.si_num as usize,
+            layout,
+        )
+        .map_err(|_| Error::Format("file format not right! - si".to_string()))?;
+        check(
+            section_index
+                .entries
+                .arr
+                .iter()
+                .skip_while(|entry| entry.sh_type != 4)
+                .all(|entry| entry.sh_type == 4),
+            "sig structure section must be last",
+        )?;
+
+        let datasections_bin = body
+            .get(header.ds_offset as usize..)
+            .ok_or_else(|| Error::Format("file format not right! - ds".to_string()))?;
+        let mut consume_size = 0;
+        for entry in section_index.entries.arr.iter() {
+            let (offset, size) = (entry.sh_offset as usize, entry.sh_size as usize);
+            //each section starts right after the previous one, aligned
+            check(offset == layout.align_up(consume_size), "ds offset")?;
+            check(
+                checked_range(datasections_bin, offset, size).is_some(),
+                "ds",
+            )?;
+            //the gap to the previous section is padding
+            check(
+                datasections_bin[consume_size..offset]
+                    .iter()
+                    .all(|b| *b == 0),
+                "padding",
+            )?;
+            consume_size = offset + size;
+        }
+
+        Ok(Self {
+            bin,
+            header,
+            section_index,
+        })
+    }
+
+    pub fn header(&self) -> &CrateHeader {
+        &self.header
+    }
+
+    ///the layout of this package, for decoding its sections
+    pub fn layout(&self) -> Layout {
+        Layout::of(self.header.c_version)
+    }
+
+    pub fn string_table(&self) -> &'a [u8] {
+        let offset = self.header.strtable_offset as usize;
+        &self.bin[offset..offset + self.header.strtable_size as usize]
+    }
+
+    pub fn section_index(&self) -> &SectionIndex {
+        &self.section_index
+    }
+
+    ///payload of the `id`th section of the index
+    pub fn section(&self, id: usize) -> Option<&'a [u8]> {
+        let entry = self.section_index.entries.arr.get(id)?;
+        let offset = self.header.ds_offset as usize + entry.sh_offset as usize;
+        Some(&self.bin[offset..offset + entry.sh_size as usize])
+    }
+
+    ///payload of the first section of type `typ`, the non-critical flag is ignored
+    pub fn section_by_type(&self, typ: Type) -> Option<&'a [u8]> {
+        let id = self
+            .section_index
+            .entries
+            .arr
+            .iter()
+            .position(|entry| section_kind(entry.sh_type) == section_kind(typ))?;
+        self.section(id)
+    }
+
+    ///the `.crate` file
+    pub fn crate_binary(&self) -> Result<&'a [u8], Error> {
+        self.section_by_type(3)
+            .ok_or_else(|| Error::Format("crate binary section not found!".to_string()))
+    }
+
+    pub fn finger_print(&self) -> &'a [u8] {
+        &self.bin[self.bin.len() - FINGERPRINT_LEN..]
+    }
+
+    pub fn check_fingerprint(&self) -> Result<bool, Error> {
+        let body = &self.bin[..self.bin.len() - FINGERPRINT_LEN];
+        Ok(PKCS::new().gen_digest_256(body)? == self.finger_print())
+    }
+
+    ///the whole file
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bin
+    }
+}
+
+///a `.scrate` file mapped into memory
+pub struct ScrateFile {
+    mmap: Mmap,
+}
+
+impl ScrateFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        //SAFETY: the map is read-only; like any reader we rely on nobody truncating
+        //or rewriting the file while it is open
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(Self { mmap })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    pub fn view(&self) -> Result<ScrateView<'_>, Error> {
+        ScrateView::new(&self.mmap)
+    }
+}
+
+#[test]
+fn test_scrate_view() {
+    use crate::utils::context::{PackageContext, PackageInfo};
+    use std::borrow::Cow;
+    use std::fs;
+
+    let mut package_context = PackageContext::new();
+    package_context.pack_info = PackageInfo::new(
+        "rust-crate".to_string(),
+        "1.0.0".to_string(),
+        "MIT".to_string(),
+        vec![],
+    );
+    package_context.add_crate_bin([15; 100].to_vec());
+    package_context
+        .features
+        .insert("default".to_string(), vec![]);
+    let (crate_package, _, bin) = package_context.encode_to_crate_package().unwrap();
+    let path = std::env::temp_dir().join(format!(
+        "cargo-crate-test-view-{}.scrate",
+        std::process::id()
+    ));
+    fs::write(&path, &bin).unwrap();
+    let file = ScrateFile::open(&path);
+    fs::remove_file(&path).unwrap();
+
+    let file = file.unwrap();
+    let view = file.view().unwrap();
+    assert!(view.check_fingerprint().unwrap());
+    assert_eq!(
+        view.header().ds_offset,
+        crate_package.crate_header.ds_offset
+    );
+    assert_eq!(view.string_table(), &*crate_package.string_table);
+    assert_eq!(view.section_index().entries.arr.len(), 4);
+    assert!(view.section_by_type(7).is_some());
+    assert!(view.section_by_type(5).is_none());
+
+    //the crate binary is the mapped bytes themselves
+    let crate_binary = view.crate_binary().unwrap();
+    assert_eq!(crate_binary, [15; 100]);
+    assert!(file.bytes().as_ptr_range().contains(&crate_binary.as_ptr()));
+
+    //and so is the decoded one, which signatures are verified over
+    let mut decoded = PackageContext::new();
+    decoded
+        .decode_from_crate_package_unverified(file.bytes())
+        .unwrap();
+    assert!(matches!(decoded.crate_binary.bytes, Cow::Borrowed(_)));
+    assert_eq!(decoded.crate_binary.bytes.as_ptr(), crate_binary.as_ptr());
+
+    assert!(matches!(
+        ScrateView::new(&bin[..bin.len() / 2]),
+        Err(Error::Format(_))
+    ));
+}
@@ -1,3 +1,4 @@
+use crate::utils::error::Error;
 use openssl::hash::{hash, MessageDigest};
 use std::fmt::{Debug, Formatter};
 use std::fs;
@@ -6,15 +7,18 @@ use std::path::Path;
 use openssl::pkcs7::Pkcs7;
 use openssl::pkcs7::Pkcs7Flags;
 use openssl::pkey::PKey;
-use openssl::stack::Stack;
-use openssl::x509::store::X509StoreBuilder;
-use openssl::x509::X509;
+use openssl::stack::{Stack, StackRef};
+use openssl::x509::store::{X509StoreBuilder, X509StoreRef};
+use openssl::x509::{X509NameRef, X509Ref, X509StoreContext, X509};
+use serde::{Deserialize, Serialize};
 
 #[derive(PartialEq)]
 pub struct PKCS {
     cert_bin: Vec<u8>,
     pkey_bin: Vec<u8>,
     root_ca_bins: Vec<Vec<u8>>,
+    ///intermediate certs embedded in the signature, pem bundles
+    chain_bins: Vec<Vec<u8>>,
 }
 
 impl Debug for PKCS {
@@ -29,14 +33,15 @@ impl PKCS {
             cert_bin: vec![],
             pkey_bin: vec![],
             root_ca_bins: vec![],
+            chain_bins: vec![],
         }
     }
-    pub fn root_ca_bins(ca_paths: Vec<String>) -> Vec<Vec<u8>> {
+    pub fn root_ca_bins(ca_paths: Vec<String>) -> Result<Vec<Vec<u8>>, Error> {
         let mut root_ca_bins = vec![];
         for ca_path in ca_paths {
-            root_ca_bins.push(fs::read(Path::new(ca_path.as_str())).unwrap());
+            root_ca_bins.push(fs::read(Path::new(ca_path.as_str()))?);
         }
-        root_ca_bins
+        Ok(root_ca_bins)
     }
 
     pub fn load_from_file_writer(
@@ -44,70 +49,232 @@ impl PKCS {
         cert_path: String,
         pkey_path: String,
         ca_paths: Vec<String>,
-    ) {
+    ) -> Result<(), Error> {
         //just for demo
-        self.cert_bin = fs::read(Path::new(cert_path.as_str())).unwrap();
-        self.pkey_bin = fs::read(Path::new(pkey_path.as_str())).unwrap();
+        self.cert_bin = fs::read(Path::new(cert_path.as_str()))?;
+        self.pkey_bin = fs::read(Path::new(pkey_path.as_str()))?;
         for ca_path in ca_paths {
             self.root_ca_bins
-                .push(fs::read(Path::new(ca_path.as_str())).unwrap());
+                .push(fs::read(Path::new(ca_path.as_str()))?);
         }
+        Ok(())
     }
 
-    pub fn load_from_file_reader(&mut self, ca_paths: Vec<String>) {
+    ///load an intermediate cert bundle that is embedded in every signature
+    pub fn load_chain_from_file(&mut self, chain_path: String) -> Result<(), Error> {
+        let chain_bin = fs::read(Path::new(chain_path.as_str()))?;
+        //fail early on a bad bundle rather than at signing time
+        X509::stack_from_pem(chain_bin.as_slice())?;
+        self.chain_bins.push(chain_bin);
+        Ok(())
+    }
+
+    pub fn load_from_file_reader(&mut self, ca_paths: Vec<String>) -> Result<(), Error> {
         //just for demo
         for ca_path in ca_paths {
             self.root_ca_bins
-                .push(fs::read(Path::new(ca_path.as_str())).unwrap());
+                .push(fs::read(Path::new(ca_path.as_str()))?);
         }
+        Ok(())
     }
 
-    pub fn encode_pkcs_bin(&self, message: &[u8]) -> Vec<u8> {
-        //FIXME current we don't support middle certs
-        let cert = X509::from_pem(self.cert_bin.as_slice()).unwrap();
-        let certs = Stack::new().unwrap();
-        let flags = Pkcs7Flags::STREAM;
-        let pkey = PKey::private_key_from_pem(self.pkey_bin.as_slice()).unwrap();
-        let mut store_builder = X509StoreBuilder::new().expect("should succeed");
+    pub fn encode_pkcs_bin(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let cert = X509::from_pem(self.cert_bin.as_slice())?;
+        let certs = cert_stack(&self.chain_bins)?;
+        //BINARY: the digest is raw bytes, smime must not translate its line endings
+        let flags = Pkcs7Flags::STREAM | Pkcs7Flags::BINARY;
+        let pkey = PKey::private_key_from_pem(self.pkey_bin.as_slice())?;
+        let mut store_builder = X509StoreBuilder::new()?;
 
         for root_ca_bin in self.root_ca_bins.iter() {
-            let root_ca = X509::from_pem(root_ca_bin.as_slice()).unwrap();
-            store_builder.add_cert(root_ca).expect("should succeed");
+            let root_ca = X509::from_pem(root_ca_bin.as_slice())?;
+            store_builder.add_cert(root_ca)?;
         }
 
         let _store = store_builder.build();
 
-        let pkcs7 = Pkcs7::sign(&cert, &pkey, &certs, message, flags).expect("should succeed");
+        let pkcs7 = Pkcs7::sign(&cert, &pkey, &certs, message, flags)?;
 
-        pkcs7.to_smime(message, flags).expect("should succeed")
+        Ok(pkcs7.to_smime(message, flags)?)
     }
 
-    pub fn decode_pkcs_bin(signed_bin: &[u8], root_ca_bins: &[Vec<u8>]) -> Vec<u8> {
+    ///verify the signed message and return its content with the signers' details.
+    ///
+    ///the signature itself is checked before the signer's certificate chain,
+    ///so a broken signature and an untrusted signer are reported apart.
+    ///the chain is built from the intermediates embedded in the signature
+    ///plus the untrusted `intermediate_bins`, and must end at one of `root_ca_bins`.
+    pub fn decode_pkcs_bin(
+        signed_bin: &[u8],
+        root_ca_bins: &[Vec<u8>],
+        intermediate_bins: &[Vec<u8>],
+    ) -> Result<Verification, Error> {
         //FIXME maybe all pkcs section should share same root cas
-        let certs = Stack::new().unwrap();
-        let flags = Pkcs7Flags::STREAM;
-        let mut store_builder = X509StoreBuilder::new().expect("should succeed");
+        let certs = cert_stack(intermediate_bins)?;
+        let flags = Pkcs7Flags::STREAM | Pkcs7Flags::BINARY;
+        let mut store_builder = X509StoreBuilder::new()?;
 
         for root_ca_bin in root_ca_bins.iter() {
-            let root_ca = X509::from_pem(root_ca_bin.as_slice()).unwrap();
-            store_builder.add_cert(root_ca).expect("should succeed");
+            let root_ca = X509::from_pem(root_ca_bin.as_slice())?;
+            store_builder.add_cert(root_ca)?;
         }
 
         let store = store_builder.build();
 
-        let (pkcs7_decoded, _content) = Pkcs7::from_smime(signed_bin).expect("should succeed");
+        let (pkcs7_decoded, _content) =
+            Pkcs7::from_smime(signed_bin).map_err(|e| Error::SignatureMismatch(e.to_string()))?;
 
         let mut output = Vec::new();
         pkcs7_decoded
-            .verify(&certs, &store, None, Some(&mut output), flags)
-            .expect("should succeed");
-        output
+            .verify(
+                &certs,
+                &store,
+                None,
+                Some(&mut output),
+                flags | Pkcs7Flags::NOVERIFY,
+            )
+            .map_err(|e| Error::SignatureMismatch(e.to_string()))?;
+
+        //PKCS7_verify only chains through the embedded certs, so each signer's chain
+        //is built here with the supplied intermediates as untrusted certs too
+        let mut untrusted = Stack::new()?;
+        if let Some(embedded) = pkcs7_decoded.signed().and_then(|s| s.certificates()) {
+            for cert in embedded.iter() {
+                untrusted.push(cert.to_owned())?;
+            }
+        }
+        for cert in certs.iter() {
+            untrusted.push(cert.to_owned())?;
+        }
+        let mut signers = vec![];
+        for signer in pkcs7_decoded.signers(&certs, flags)?.iter() {
+            let mut signer_cert = SignerCert::from_x509(signer)?;
+            signer_cert.root = chain_root(&store, signer, &untrusted)?;
+            signers.push(signer_cert);
+        }
+        Ok(Verification {
+            content: output,
+            signers,
+        })
+    }
+
+    ///details of each certificate that signed the message, nothing is verified
+    pub fn signer_certs(signed_bin: &[u8]) -> Vec<SignerCert> {
+        let mut signer_certs = vec![];
+        if let Ok((pkcs7, _content)) = Pkcs7::from_smime(signed_bin) {
+            let Ok(certs) = Stack::new() else {
+                return signer_certs;
+            };
+            if let Ok(signers) = pkcs7.signers(&certs, Pkcs7Flags::empty()) {
+                for signer in signers.iter() {
+                    if let Ok(signer_cert) = SignerCert::from_x509(signer) {
+                        signer_certs.push(signer_cert);
+                    }
+                }
+            }
+        }
+        signer_certs
+    }
+
+    pub fn gen_digest_256(&self, bin: &[u8]) -> Result<Vec<u8>, Error> {
+        let res = hash(MessageDigest::sha256(), bin)?;
+        Ok(res.to_vec())
+    }
+}
+
+///a verified signed message
+#[derive(Debug)]
+pub struct Verification {
+    pub content: Vec<u8>,
+    pub signers: Vec<SignerCert>,
+}
+
+///certificate that made a signature
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SignerCert {
+    pub subject: String,
+    pub issuer: String,
+    ///hex
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    ///hex digest of the DER certificate
+    pub sha256_fingerprint: String,
+    ///digest algorithm of the signer certificate's own signature
+    pub cert_signature_digest: String,
+    ///subject of the trusted root the chain ends at, empty if not verified
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub root: String,
+}
+
+impl SignerCert {
+    fn from_x509(cert: &X509Ref) -> Result<Self, Error> {
+        Ok(Self {
+            subject: x509_name_to_string(cert.subject_name()),
+            issuer: x509_name_to_string(cert.issuer_name()),
+            serial: cert.serial_number().to_bn()?.to_hex_str()?.to_string(),
+            not_before: cert.not_before().to_string(),
+            not_after: cert.not_after().to_string(),
+            sha256_fingerprint: cert
+                .digest(MessageDigest::sha256())?
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            cert_signature_digest: cert
+                .signature_algorithm()
+                .object()
+                .nid()
+                .signature_algorithms()
+                .and_then(|algorithms| algorithms.digest.long_name().ok())
+                .unwrap_or_default()
+                .to_string(),
+            root: String::new(),
+        })
     }
+}
+
+///subject of the root `cert` chains to through `store`
+fn chain_root(
+    store: &X509StoreRef,
+    cert: &X509Ref,
+    untrusted: &StackRef<X509>,
+) -> Result<String, Error> {
+    let mut ctx = X509StoreContext::new()?;
+    ctx.init(store, cert, untrusted, |ctx| {
+        if !ctx.verify_cert()? {
+            return Ok(Err(Error::UntrustedSigner(
+                ctx.error().error_string().to_string(),
+            )));
+        }
+        Ok(Ok(ctx
+            .chain()
+            .and_then(|chain| chain.iter().last())
+            .map(|root| x509_name_to_string(root.subject_name()))
+            .unwrap_or_default()))
+    })?
+}
 
-    pub fn gen_digest_256(&self, bin: &[u8]) -> Vec<u8> {
-        let res = hash(MessageDigest::sha256(), bin).unwrap();
-        res.to_vec()
+///all certs of the pem bundles, in order
+fn cert_stack(pem_bins: &[Vec<u8>]) -> Result<Stack<X509>, Error> {
+    let mut certs = Stack::new()?;
+    for pem_bin in pem_bins.iter() {
+        for cert in X509::stack_from_pem(pem_bin.as_slice())? {
+            certs.push(cert)?;
+        }
     }
+    Ok(certs)
+}
+
+///format name as `C=AU, ST=Some-State, CN=foobar.com`
+pub fn x509_name_to_string(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let val = String::from_utf8_lossy(entry.data().as_slice());
+            format!("{}={}", key, val)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 impl Default for PKCS {
@@ -185,3 +352,80 @@ impl Default for PKCS {
 //     //assert_eq!(&*res, spec);
 //     Ok(())
 // }
+
+#[test]
+fn test_pkcs_binary_content() {
+    let mut pkcs = PKCS::new();
+    pkcs.load_from_file_writer(
+        "test/cert.pem".to_string(),
+        "test/key.pem".to_string(),
+        ["test/root-ca.pem".to_string()].to_vec(),
+    )
+    .unwrap();
+    let message = [b'a', b'\n', b'b', b'\r', 0, b'\r', b'\n'];
+    let signed = pkcs.encode_pkcs_bin(&message).unwrap();
+    let root_cas = PKCS::root_ca_bins(["test/root-ca.pem".to_string()].to_vec()).unwrap();
+    assert_eq!(
+        PKCS::decode_pkcs_bin(&signed, &root_cas, &[])
+            .unwrap()
+            .content,
+        message
+    );
+}
+
+#[test]
+fn test_pkcs_chain() {
+    let message = [7; 32];
+    let root_cas = PKCS::root_ca_bins(["test/root-ca.pem".to_string()].to_vec()).unwrap();
+    let intermediates = [std::fs::read("test/intermediate-ca.pem").unwrap()];
+    let mut pkcs = PKCS::new();
+    pkcs.load_from_file_writer(
+        "test/chain-cert.pem".to_string(),
+        "test/key.pem".to_string(),
+        ["test/root-ca.pem".to_string()].to_vec(),
+    )
+    .unwrap();
+
+    //leaf only, the verifier has to supply the intermediate
+    let signed = pkcs.encode_pkcs_bin(&message).unwrap();
+    assert!(matches!(
+        PKCS::decode_pkcs_bin(&signed, &root_cas, &[]),
+        Err(Error::UntrustedSigner(_))
+    ));
+    let verification = PKCS::decode_pkcs_bin(&signed, &root_cas, &intermediates).unwrap();
+    assert_eq!(verification.content, message);
+    assert_eq!(verification.signers.len(), 1);
+    let signer = &verification.signers[0];
+    assert!(signer.subject.ends_with("CN=chain.foobar.com"));
+    assert_eq!(signer.cert_signature_digest, "sha256");
+    assert_eq!(signer.sha256_fingerprint.len(), 64);
+    assert_eq!(
+        signer.root,
+        x509_name_to_string(X509::from_pem(&root_cas[0]).unwrap().subject_name())
+    );
+
+    //intermediate embedded in the signature
+    pkcs.load_chain_from_file("test/intermediate-ca.pem".to_string())
+        .unwrap();
+    let signed = pkcs.encode_pkcs_bin(&message).unwrap();
+    assert_eq!(
+        PKCS::decode_pkcs_bin(&signed, &root_cas, &[])
+            .unwrap()
+            .content,
+        message
+    );
+    assert!(matches!(
+        PKCS::decode_pkcs_bin(&signed, &[], &[]),
+        Err(Error::UntrustedSigner(_))
+    ));
+
+    //a root smuggled in as an intermediate is not a trust anchor
+    let bundle = [
+        std::fs::read("test/intermediate-ca.pem").unwrap(),
+        std::fs::read("test/root-ca.pem").unwrap(),
+    ];
+    assert!(matches!(
+        PKCS::decode_pkcs_bin(&signed, &[], &bundle),
+        Err(Error::UntrustedSigner(_))
+    ));
+}
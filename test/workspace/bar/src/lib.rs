@@ -0,0 +1 @@
+//! workspace member used by the tests
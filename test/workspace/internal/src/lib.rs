@@ -0,0 +1 @@
+//! unpublished workspace member used by the tests